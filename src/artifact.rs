@@ -0,0 +1,112 @@
+use crate::error::{BoxError, Error, Result};
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+pub type ArtifactFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, BoxError>> + Send + 'a>>;
+
+/// Where an artifact required by a test-environment comes from, e.g. the code of a Lambda.
+///
+/// Implemented for downloads ([`HttpArtifact`]), local files ([`FileArtifact`]) and bytes already
+/// in memory ([`InlineArtifact`]). Implement it to fetch artifacts from anywhere else.
+pub trait ArtifactSource: fmt::Debug + Send + Sync {
+    /// Describes where the artifact is fetched from, e.g. its URL.
+    fn location(&self) -> String;
+
+    fn fetch(&self) -> ArtifactFuture<'_>;
+}
+
+/// Fetches the artifact of `source`, failing with [`Error::ArtifactFetch`].
+pub async fn fetch_artifact(source: &dyn ArtifactSource) -> Result<Vec<u8>> {
+    source
+        .fetch()
+        .await
+        .map_err(|source_error| Error::ArtifactFetch {
+            artifact: source.location(),
+            source: source_error,
+        })
+}
+
+/// Downloads the artifact via HTTP(S).
+#[derive(Debug, Clone)]
+pub struct HttpArtifact {
+    url: String,
+}
+
+impl HttpArtifact {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl ArtifactSource for HttpArtifact {
+    fn location(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch(&self) -> ArtifactFuture<'_> {
+        Box::pin(async move {
+            let bytes = reqwest::get(&self.url)
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            Ok(bytes.to_vec())
+        })
+    }
+}
+
+/// Reads the artifact from a local file, e.g. one built or cached by CI.
+#[derive(Debug, Clone)]
+pub struct FileArtifact {
+    path: PathBuf,
+}
+
+impl FileArtifact {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ArtifactSource for FileArtifact {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn fetch(&self) -> ArtifactFuture<'_> {
+        Box::pin(async move { Ok(tokio::fs::read(&self.path).await?) })
+    }
+}
+
+/// An artifact already in memory, e.g. embedded via `include_bytes!`.
+#[derive(Clone)]
+pub struct InlineArtifact {
+    bytes: Vec<u8>,
+}
+
+impl InlineArtifact {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+}
+
+impl fmt::Debug for InlineArtifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InlineArtifact")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl ArtifactSource for InlineArtifact {
+    fn location(&self) -> String {
+        format!("<{} inline bytes>", self.bytes.len())
+    }
+
+    fn fetch(&self) -> ArtifactFuture<'_> {
+        Box::pin(async move { Ok(self.bytes.clone()) })
+    }
+}
@@ -0,0 +1,604 @@
+//! Derives [`TableSpec`]s from the `AWS::DynamoDB::Table`- and `AWS::Serverless::SimpleTable`-
+//! resources of a CloudFormation- or SAM-template in YAML or JSON.
+//!
+//! Intrinsic functions are resolved as far as possible without deploying the stack: `Ref` to
+//! parameters, `Fn::Sub` and `Fn::Join`, both in short (`!Sub`) and long form. Parameters resolve
+//! to the values set via [`CloudFormationTemplate::with_parameter`], falling back to their
+//! `Default`. Tables without `TableName` are named after their logical id. Only the properties a
+//! [`TableSpec`] is derived from are resolved, so all others, e.g. `Tags`, may use any intrinsic
+//! function.
+
+use crate::dynamodb::register_table_spec;
+use crate::dynamodb::spec::{
+    AttributeType, BillingMode, IndexSpec, Projection, StreamViewType, TableClass, TableSpec,
+    Throughput,
+};
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub const DYNAMODB_TABLE_TYPE: &str = "AWS::DynamoDB::Table";
+pub const SAM_SIMPLE_TABLE_TYPE: &str = "AWS::Serverless::SimpleTable";
+
+/// A parsed CloudFormation- or SAM-template.
+///
+/// ```no_run
+/// # fn example() -> test_api::Result<()> {
+/// use test_api::dynamodb::cloudformation::CloudFormationTemplate;
+///
+/// let specs = CloudFormationTemplate::from_file("infrastructure/template.yaml")?
+///     .with_parameter("Stage", "test")
+///     .table_specs()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CloudFormationTemplate {
+    name: String,
+    template: Template,
+    parameters: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Template {
+    #[serde(default)]
+    parameters: BTreeMap<String, Parameter>,
+    #[serde(default)]
+    resources: BTreeMap<String, Resource>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Parameter {
+    default: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Resource {
+    #[serde(rename = "Type")]
+    resource_type: String,
+    #[serde(default)]
+    properties: Value,
+}
+
+impl CloudFormationTemplate {
+    /// Parses `template` in YAML or JSON, which is a subset of YAML.
+    pub fn parse(template: &str) -> Result<Self> {
+        Self::parse_named("<template>", template)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let template = std::fs::read_to_string(path).map_err(|e| template_error(&name, e))?;
+        Self::parse_named(&name, &template)
+    }
+
+    fn parse_named(name: &str, template: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            template: serde_yaml::from_str(template).map_err(|e| template_error(name, e))?,
+            parameters: HashMap::new(),
+        })
+    }
+
+    /// Sets the value of the parameter or pseudo-parameter (e.g. `AWS::StackName`) `name`.
+    pub fn with_parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parameters.insert(name.into(), value.into());
+        self
+    }
+
+    /// Converts every table of the template, ordered by logical id.
+    pub fn table_specs(&self) -> Result<Vec<TableSpec>> {
+        self.template
+            .resources
+            .iter()
+            .filter_map(|(logical_id, resource)| {
+                let table_spec = match resource.resource_type.as_str() {
+                    DYNAMODB_TABLE_TYPE => Self::table_spec,
+                    SAM_SIMPLE_TABLE_TYPE => Self::simple_table_spec,
+                    _ => return None,
+                };
+                Some(
+                    table_spec(self, logical_id, &resource.properties).map_err(|reason| {
+                        template_error(&self.name, format!("resource '{logical_id}': {reason}"))
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    /// Registers every table of the template to be created by [`init`](super::init).
+    pub fn register_table_specs(&self) -> Result<()> {
+        self.table_specs()?
+            .into_iter()
+            .for_each(register_table_spec);
+        Ok(())
+    }
+
+    fn table_spec(&self, logical_id: &str, properties: &Value) -> Result<TableSpec, String> {
+        let properties: TableProperties = self.resolve_into(properties, TABLE_FIELDS)?;
+        let key = KeySpec::from_key_schema(&properties.key_schema)?;
+        let provisioned_throughput = properties
+            .provisioned_throughput
+            .map(ThroughputProperty::into_throughput)
+            .transpose()?;
+        // Unlike the API, CloudFormation defaults to provisioned capacity.
+        let billing_mode = match properties.billing_mode.as_deref() {
+            Some("PAY_PER_REQUEST") => BillingMode::PayPerRequest,
+            Some("PROVISIONED") | None => BillingMode::Provisioned,
+            Some(other) => return Err(format!("unknown BillingMode '{other}'")),
+        };
+        Ok(TableSpec {
+            table_name: properties
+                .table_name
+                .unwrap_or_else(|| logical_id.to_string()),
+            attributes: properties
+                .attribute_definitions
+                .into_iter()
+                .map(|definition| {
+                    Ok((
+                        definition.attribute_name,
+                        parse_attribute_type(&definition.attribute_type)?,
+                    ))
+                })
+                .collect::<Result<_, String>>()?,
+            hash_key: key.hash_key,
+            range_key: key.range_key,
+            global_secondary_indexes: properties
+                .global_secondary_indexes
+                .into_iter()
+                .map(IndexProperty::into_index_spec)
+                .collect::<Result<_, _>>()?,
+            local_secondary_indexes: properties
+                .local_secondary_indexes
+                .into_iter()
+                .map(IndexProperty::into_index_spec)
+                .collect::<Result<_, _>>()?,
+            billing_mode,
+            provisioned_throughput,
+            table_class: match properties.table_class.as_deref() {
+                Some("STANDARD") | None => TableClass::Standard,
+                Some("STANDARD_INFREQUENT_ACCESS") => TableClass::StandardInfrequentAccess,
+                Some(other) => return Err(format!("unknown TableClass '{other}'")),
+            },
+            time_to_live_attribute: properties
+                .time_to_live_specification
+                .filter(|time_to_live| time_to_live.enabled)
+                .map(|time_to_live| time_to_live.attribute_name),
+            stream_view_type: properties
+                .stream_specification
+                .map(|stream| parse_stream_view_type(&stream.stream_view_type))
+                .transpose()?,
+        })
+    }
+
+    /// Converts a SAM-table, which only has a hash-key and defaults to on-demand capacity.
+    fn simple_table_spec(&self, logical_id: &str, properties: &Value) -> Result<TableSpec, String> {
+        let properties: SimpleTableProperties =
+            self.resolve_into(properties, SIMPLE_TABLE_FIELDS)?;
+        let primary_key = properties.primary_key.unwrap_or_else(|| PrimaryKey {
+            name: "id".to_string(),
+            key_type: "String".to_string(),
+        });
+        let attribute_type = match primary_key.key_type.as_str() {
+            "String" => AttributeType::S,
+            "Number" => AttributeType::N,
+            "Binary" => AttributeType::B,
+            other => return Err(format!("unknown PrimaryKey-Type '{other}'")),
+        };
+        let provisioned_throughput = properties
+            .provisioned_throughput
+            .map(ThroughputProperty::into_throughput)
+            .transpose()?;
+        Ok(TableSpec {
+            table_name: properties
+                .table_name
+                .unwrap_or_else(|| logical_id.to_string()),
+            attributes: BTreeMap::from([(primary_key.name.clone(), attribute_type)]),
+            hash_key: primary_key.name,
+            billing_mode: match provisioned_throughput {
+                Some(_) => BillingMode::Provisioned,
+                None => BillingMode::PayPerRequest,
+            },
+            provisioned_throughput,
+            ..TableSpec::default()
+        })
+    }
+
+    fn resolve_into<T: DeserializeOwned>(
+        &self,
+        value: &Value,
+        fields: &[Field],
+    ) -> Result<T, String> {
+        serde_yaml::from_value(self.resolve_fields(value, fields)?).map_err(|e| e.to_string())
+    }
+
+    /// Resolves `fields` of `value`, dropping all other fields.
+    ///
+    /// Applies to every element of a sequence. Fields without nested fields are resolved entirely.
+    fn resolve_fields(&self, value: &Value, fields: &[Field]) -> Result<Value, String> {
+        match value {
+            Value::Mapping(mapping)
+                if !fields.is_empty() && intrinsic_function(mapping).is_none() =>
+            {
+                fields
+                    .iter()
+                    .filter_map(|field| {
+                        let value = mapping.get(field.name)?;
+                        Some(
+                            self.resolve_fields(value, field.fields)
+                                .map(|value| (Value::String(field.name.to_string()), value)),
+                        )
+                    })
+                    .collect::<Result<Mapping, String>>()
+                    .map(Value::Mapping)
+            }
+            Value::Sequence(values) if !fields.is_empty() => values
+                .iter()
+                .map(|value| self.resolve_fields(value, fields))
+                .collect::<Result<_, _>>()
+                .map(Value::Sequence),
+            value => self.resolve(value),
+        }
+    }
+
+    /// Replaces all intrinsic functions in `value` by their result.
+    fn resolve(&self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::Tagged(tagged) => {
+                let function = tagged.tag.to_string();
+                self.resolve_function(function.trim_start_matches('!'), &tagged.value)
+            }
+            Value::Mapping(mapping) => {
+                if let Some((function, argument)) = intrinsic_function(mapping) {
+                    return self.resolve_function(function, argument);
+                }
+                mapping
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.resolve(value)?)))
+                    .collect::<Result<Mapping, String>>()
+                    .map(Value::Mapping)
+            }
+            Value::Sequence(values) => values
+                .iter()
+                .map(|value| self.resolve(value))
+                .collect::<Result<_, _>>()
+                .map(Value::Sequence),
+            value => Ok(value.clone()),
+        }
+    }
+
+    fn resolve_function(&self, function: &str, argument: &Value) -> Result<Value, String> {
+        let argument = self.resolve(argument)?;
+        match (function, argument) {
+            ("Ref", Value::String(name)) => self.parameter(&name).map(Value::String),
+            ("Sub", Value::String(template)) => self
+                .substitute(&template, &Mapping::new())
+                .map(Value::String),
+            ("Sub", Value::Sequence(arguments)) => match arguments.as_slice() {
+                [Value::String(template), Value::Mapping(variables)] => {
+                    self.substitute(template, variables).map(Value::String)
+                }
+                _ => Err("Fn::Sub expects a string and a mapping of variables".to_string()),
+            },
+            ("Join", Value::Sequence(arguments)) => match arguments.as_slice() {
+                [Value::String(delimiter), Value::Sequence(values)] => values
+                    .iter()
+                    .map(scalar_to_string)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|values| Value::String(values.join(delimiter))),
+                _ => Err("Fn::Join expects a delimiter and a list of values".to_string()),
+            },
+            (function, _) => Err(format!(
+                "intrinsic function '{function}' isn't supported in table-properties"
+            )),
+        }
+    }
+
+    fn parameter(&self, name: &str) -> Result<String, String> {
+        if let Some(value) = self.parameters.get(name) {
+            return Ok(value.clone());
+        }
+        match self
+            .template
+            .parameters
+            .get(name)
+            .and_then(|parameter| parameter.default.as_ref())
+        {
+            Some(default) => scalar_to_string(default),
+            None => Err(format!(
+                "parameter '{name}' has no default, set it via `with_parameter`"
+            )),
+        }
+    }
+
+    /// Replaces `${name}` in `template` by the variable or parameter `name`, and `${!name}` by
+    /// `${name}`.
+    fn substitute(&self, template: &str, variables: &Mapping) -> Result<String, String> {
+        let mut result = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unterminated variable in '{template}'"))?;
+            result.push_str(&rest[..start]);
+            let name = &rest[start + 2..end];
+            match name.strip_prefix('!') {
+                Some(literal) => result.push_str(&format!("${{{literal}}}")),
+                None => match variables.get(name) {
+                    Some(value) => result.push_str(&scalar_to_string(value)?),
+                    None => result.push_str(&self.parameter(name)?),
+                },
+            }
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+/// The function and argument of an intrinsic function in long form, e.g. `{"Fn::Sub": "..."}`.
+fn intrinsic_function(mapping: &Mapping) -> Option<(&str, &Value)> {
+    if mapping.len() != 1 {
+        return None;
+    }
+    match mapping.iter().next()? {
+        (Value::String(key), argument) if key == "Ref" || key.starts_with("Fn::") => {
+            Some((key.trim_start_matches("Fn::"), argument))
+        }
+        _ => None,
+    }
+}
+
+fn scalar_to_string(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(string) => Ok(string.clone()),
+        Value::Number(number) => Ok(number.to_string()),
+        Value::Bool(bool) => Ok(bool.to_string()),
+        other => Err(format!("expected a scalar but got {other:?}")),
+    }
+}
+
+fn template_error(name: &str, source: impl Into<crate::error::BoxError>) -> Error {
+    Error::Fixture {
+        name: name.to_string(),
+        source: source.into(),
+    }
+}
+
+fn parse_attribute_type(attribute_type: &str) -> Result<AttributeType, String> {
+    match attribute_type {
+        "S" => Ok(AttributeType::S),
+        "N" => Ok(AttributeType::N),
+        "B" => Ok(AttributeType::B),
+        other => Err(format!("unknown AttributeType '{other}'")),
+    }
+}
+
+fn parse_stream_view_type(stream_view_type: &str) -> Result<StreamViewType, String> {
+    match stream_view_type {
+        "KEYS_ONLY" => Ok(StreamViewType::KeysOnly),
+        "NEW_IMAGE" => Ok(StreamViewType::NewImage),
+        "OLD_IMAGE" => Ok(StreamViewType::OldImage),
+        "NEW_AND_OLD_IMAGES" => Ok(StreamViewType::NewAndOldImages),
+        other => Err(format!("unknown StreamViewType '{other}'")),
+    }
+}
+
+/// A property read from a resource, together with the nested properties read from it, if any.
+struct Field {
+    name: &'static str,
+    fields: &'static [Field],
+}
+
+impl Field {
+    const fn leaf(name: &'static str) -> Self {
+        Self { name, fields: &[] }
+    }
+
+    const fn nested(name: &'static str, fields: &'static [Field]) -> Self {
+        Self { name, fields }
+    }
+}
+
+const KEY_SCHEMA_FIELDS: &[Field] = &[Field::leaf("AttributeName"), Field::leaf("KeyType")];
+const THROUGHPUT_FIELDS: &[Field] = &[
+    Field::leaf("ReadCapacityUnits"),
+    Field::leaf("WriteCapacityUnits"),
+];
+const INDEX_FIELDS: &[Field] = &[
+    Field::leaf("IndexName"),
+    Field::nested("KeySchema", KEY_SCHEMA_FIELDS),
+    Field::nested(
+        "Projection",
+        &[
+            Field::leaf("ProjectionType"),
+            Field::leaf("NonKeyAttributes"),
+        ],
+    ),
+    Field::nested("ProvisionedThroughput", THROUGHPUT_FIELDS),
+];
+
+/// The fields of [`TableProperties`].
+const TABLE_FIELDS: &[Field] = &[
+    Field::leaf("TableName"),
+    Field::nested(
+        "AttributeDefinitions",
+        &[Field::leaf("AttributeName"), Field::leaf("AttributeType")],
+    ),
+    Field::nested("KeySchema", KEY_SCHEMA_FIELDS),
+    Field::nested("GlobalSecondaryIndexes", INDEX_FIELDS),
+    Field::nested("LocalSecondaryIndexes", INDEX_FIELDS),
+    Field::leaf("BillingMode"),
+    Field::nested("ProvisionedThroughput", THROUGHPUT_FIELDS),
+    Field::leaf("TableClass"),
+    Field::nested(
+        "TimeToLiveSpecification",
+        &[Field::leaf("AttributeName"), Field::leaf("Enabled")],
+    ),
+    Field::nested("StreamSpecification", &[Field::leaf("StreamViewType")]),
+];
+
+/// The fields of [`SimpleTableProperties`].
+const SIMPLE_TABLE_FIELDS: &[Field] = &[
+    Field::leaf("TableName"),
+    Field::nested("PrimaryKey", &[Field::leaf("Name"), Field::leaf("Type")]),
+    Field::nested("ProvisionedThroughput", THROUGHPUT_FIELDS),
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TableProperties {
+    table_name: Option<String>,
+    #[serde(default)]
+    attribute_definitions: Vec<AttributeDefinitionProperty>,
+    key_schema: Vec<KeySchemaProperty>,
+    #[serde(default)]
+    global_secondary_indexes: Vec<IndexProperty>,
+    #[serde(default)]
+    local_secondary_indexes: Vec<IndexProperty>,
+    billing_mode: Option<String>,
+    provisioned_throughput: Option<ThroughputProperty>,
+    table_class: Option<String>,
+    time_to_live_specification: Option<TimeToLiveProperty>,
+    stream_specification: Option<StreamProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SimpleTableProperties {
+    table_name: Option<String>,
+    primary_key: Option<PrimaryKey>,
+    provisioned_throughput: Option<ThroughputProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PrimaryKey {
+    name: String,
+    #[serde(rename = "Type")]
+    key_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AttributeDefinitionProperty {
+    attribute_name: String,
+    attribute_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct KeySchemaProperty {
+    attribute_name: String,
+    key_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IndexProperty {
+    index_name: String,
+    key_schema: Vec<KeySchemaProperty>,
+    projection: ProjectionProperty,
+    provisioned_throughput: Option<ThroughputProperty>,
+}
+
+impl IndexProperty {
+    fn into_index_spec(self) -> Result<IndexSpec, String> {
+        let key = KeySpec::from_key_schema(&self.key_schema)
+            .map_err(|reason| format!("index '{}': {reason}", self.index_name))?;
+        Ok(IndexSpec {
+            projection: match self.projection.projection_type.as_deref() {
+                Some("ALL") => Projection::All,
+                // CloudFormation defaults to keys-only if no projection-type is given.
+                Some("KEYS_ONLY") | None => Projection::KeysOnly,
+                Some("INCLUDE") => Projection::Include {
+                    non_key_attributes: self.projection.non_key_attributes,
+                },
+                Some(other) => {
+                    return Err(format!(
+                        "index '{}': unknown ProjectionType '{other}'",
+                        self.index_name
+                    ));
+                }
+            },
+            provisioned_throughput: self
+                .provisioned_throughput
+                .map(ThroughputProperty::into_throughput)
+                .transpose()?,
+            index_name: self.index_name,
+            hash_key: key.hash_key,
+            range_key: key.range_key,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProjectionProperty {
+    projection_type: Option<String>,
+    #[serde(default)]
+    non_key_attributes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ThroughputProperty {
+    read_capacity_units: Value,
+    write_capacity_units: Value,
+}
+
+impl ThroughputProperty {
+    /// Templates often quote numbers, e.g. when they come from parameters.
+    fn into_throughput(self) -> Result<Throughput, String> {
+        let capacity_units = |value: &Value| {
+            scalar_to_string(value)?
+                .parse::<i64>()
+                .map_err(|e| format!("invalid capacity-units {value:?}: {e}"))
+        };
+        Ok(Throughput {
+            read_capacity_units: capacity_units(&self.read_capacity_units)?,
+            write_capacity_units: capacity_units(&self.write_capacity_units)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TimeToLiveProperty {
+    attribute_name: String,
+    enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StreamProperty {
+    stream_view_type: String,
+}
+
+struct KeySpec {
+    hash_key: String,
+    range_key: Option<String>,
+}
+
+impl KeySpec {
+    fn from_key_schema(key_schema: &[KeySchemaProperty]) -> Result<Self, String> {
+        let key_of_type = |key_type: &str| {
+            key_schema
+                .iter()
+                .find(|element| element.key_type == key_type)
+                .map(|element| element.attribute_name.clone())
+        };
+        Ok(Self {
+            hash_key: key_of_type("HASH").ok_or("KeySchema has no HASH-key")?,
+            range_key: key_of_type("RANGE"),
+        })
+    }
+}
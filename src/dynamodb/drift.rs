@@ -0,0 +1,443 @@
+//! Detects drift between [`TableSpec`]s and the tables actually present in DynamoDB.
+
+use crate::dynamodb::registered_table_specs;
+use crate::dynamodb::spec::{
+    AttributeType, BillingMode, IndexSpec, Projection, StreamViewType, TableSpec,
+};
+use crate::error::Result;
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::operation::describe_table::DescribeTableError;
+use aws_sdk_dynamodb::types::{
+    KeySchemaElement, KeyType, ProjectionType, ScalarAttributeType, TableDescription,
+    TimeToLiveStatus,
+};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A difference between the expected and the live definition of a table.
+///
+/// `index` is `None` for differences of the table itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    MissingTable {
+        table: String,
+    },
+    WrongKey {
+        table: String,
+        index: Option<String>,
+        key_type: KeyType,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+    WrongAttributeType {
+        table: String,
+        attribute: String,
+        expected: AttributeType,
+        actual: Option<AttributeType>,
+    },
+    MissingIndex {
+        table: String,
+        index: String,
+    },
+    UnexpectedIndex {
+        table: String,
+        index: String,
+    },
+    WrongProjection {
+        table: String,
+        index: String,
+        expected: Projection,
+        actual: Option<Projection>,
+    },
+    WrongBillingMode {
+        table: String,
+        expected: BillingMode,
+        actual: BillingMode,
+    },
+    WrongStream {
+        table: String,
+        expected: Option<StreamViewType>,
+        actual: Option<StreamViewType>,
+    },
+    WrongTimeToLive {
+        table: String,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::MissingTable { table } => write!(f, "table '{table}' is missing"),
+            Drift::WrongKey {
+                table,
+                index,
+                key_type,
+                expected,
+                actual,
+            } => {
+                let location = match index {
+                    Some(index) => format!("index '{index}' of table '{table}'"),
+                    None => format!("table '{table}'"),
+                };
+                write!(
+                    f,
+                    "{location} has {key_type}-key {actual:?} instead of {expected:?}",
+                    key_type = key_type.as_str()
+                )
+            }
+            Drift::WrongAttributeType {
+                table,
+                attribute,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "attribute '{attribute}' of table '{table}' has type {actual:?} instead of \
+                 {expected:?}"
+            ),
+            Drift::MissingIndex { table, index } => {
+                write!(f, "index '{index}' of table '{table}' is missing")
+            }
+            Drift::UnexpectedIndex { table, index } => {
+                write!(f, "table '{table}' has unexpected index '{index}'")
+            }
+            Drift::WrongProjection {
+                table,
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "index '{index}' of table '{table}' projects {actual:?} instead of {expected:?}"
+            ),
+            Drift::WrongBillingMode {
+                table,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "table '{table}' is billed {actual:?} instead of {expected:?}"
+            ),
+            Drift::WrongStream {
+                table,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "table '{table}' streams {actual:?} instead of {expected:?}"
+            ),
+            Drift::WrongTimeToLive {
+                table,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "table '{table}' expires items by {actual:?} instead of {expected:?}"
+            ),
+        }
+    }
+}
+
+/// All [`Drift`]s found by [`detect_drift`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriftReport {
+    pub drifts: Vec<Drift>,
+}
+
+impl DriftReport {
+    pub fn is_empty(&self) -> bool {
+        self.drifts.is_empty()
+    }
+
+    /// Panics listing every drift, if any.
+    #[track_caller]
+    pub fn assert_no_drift(&self) {
+        if !self.is_empty() {
+            panic!("DynamoDB-tables drifted from their specification:\n{self}");
+        }
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for drift in &self.drifts {
+            writeln!(f, "- {drift}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes the table of every spec in `specs` and reports how it differs from its spec.
+///
+/// Attributes not used as key of the table or an index aren't compared, because DynamoDB doesn't
+/// report them.
+pub async fn detect_drift(client: &Client, specs: &[TableSpec]) -> Result<DriftReport> {
+    let mut drifts = Vec::new();
+    for spec in specs {
+        let table = match client
+            .describe_table()
+            .table_name(&spec.table_name)
+            .send()
+            .await
+        {
+            Ok(output) => output.table,
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(DescribeTableError::is_resource_not_found_exception) =>
+            {
+                None
+            }
+            Err(e) => return Err(aws_sdk_dynamodb::Error::from(e).into()),
+        };
+        let Some(table) = table else {
+            drifts.push(Drift::MissingTable {
+                table: spec.table_name.clone(),
+            });
+            continue;
+        };
+        let time_to_live_attribute = client
+            .describe_time_to_live()
+            .table_name(&spec.table_name)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?
+            .time_to_live_description
+            .filter(|description| {
+                matches!(
+                    description.time_to_live_status(),
+                    Some(TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)
+                )
+            })
+            .and_then(|description| description.attribute_name);
+        diff_table(spec, &table, time_to_live_attribute, &mut drifts);
+    }
+    Ok(DriftReport { drifts })
+}
+
+/// Detects drift of all [registered tables](registered_table_specs) and panics if any.
+pub async fn assert_no_drift(client: &Client) {
+    detect_drift(client, &registered_table_specs())
+        .await
+        .expect("shouldn't fail describing DynamoDB-tables")
+        .assert_no_drift();
+}
+
+fn diff_table(
+    spec: &TableSpec,
+    table: &TableDescription,
+    time_to_live_attribute: Option<String>,
+    drifts: &mut Vec<Drift>,
+) {
+    let table_name = &spec.table_name;
+    diff_key(
+        table_name,
+        None,
+        spec.hash_key.as_str(),
+        spec.range_key.as_deref(),
+        table.key_schema(),
+        drifts,
+    );
+
+    for (attribute, expected) in &spec.attributes {
+        let actual = table
+            .attribute_definitions()
+            .iter()
+            .find(|definition| definition.attribute_name() == attribute)
+            .and_then(|definition| attribute_type_from_sdk(definition.attribute_type()));
+        if actual != Some(*expected) {
+            drifts.push(Drift::WrongAttributeType {
+                table: table_name.clone(),
+                attribute: attribute.clone(),
+                expected: *expected,
+                actual,
+            });
+        }
+    }
+
+    let global_secondary_indexes = table
+        .global_secondary_indexes()
+        .iter()
+        .map(|index| (index.index_name(), index.key_schema(), index.projection()))
+        .collect::<Vec<_>>();
+    diff_indexes(
+        table_name,
+        &spec.global_secondary_indexes,
+        &global_secondary_indexes,
+        drifts,
+    );
+    let local_secondary_indexes = table
+        .local_secondary_indexes()
+        .iter()
+        .map(|index| (index.index_name(), index.key_schema(), index.projection()))
+        .collect::<Vec<_>>();
+    diff_indexes(
+        table_name,
+        &spec.local_secondary_indexes,
+        &local_secondary_indexes,
+        drifts,
+    );
+
+    // Tables created with provisioned capacity don't necessarily report a billing-mode.
+    let billing_mode = match table
+        .billing_mode_summary()
+        .and_then(|summary| summary.billing_mode())
+    {
+        Some(aws_sdk_dynamodb::types::BillingMode::PayPerRequest) => BillingMode::PayPerRequest,
+        _ => BillingMode::Provisioned,
+    };
+    if billing_mode != spec.billing_mode {
+        drifts.push(Drift::WrongBillingMode {
+            table: table_name.clone(),
+            expected: spec.billing_mode,
+            actual: billing_mode,
+        });
+    }
+
+    let stream_view_type = table
+        .stream_specification()
+        .filter(|stream| stream.stream_enabled())
+        .and_then(|stream| stream.stream_view_type())
+        .and_then(stream_view_type_from_sdk);
+    if stream_view_type != spec.stream_view_type {
+        drifts.push(Drift::WrongStream {
+            table: table_name.clone(),
+            expected: spec.stream_view_type,
+            actual: stream_view_type,
+        });
+    }
+
+    if time_to_live_attribute != spec.time_to_live_attribute {
+        drifts.push(Drift::WrongTimeToLive {
+            table: table_name.clone(),
+            expected: spec.time_to_live_attribute.clone(),
+            actual: time_to_live_attribute,
+        });
+    }
+}
+
+type IndexDescription<'a> = (
+    Option<&'a str>,
+    &'a [KeySchemaElement],
+    Option<&'a aws_sdk_dynamodb::types::Projection>,
+);
+
+fn diff_indexes(
+    table: &str,
+    specs: &[IndexSpec],
+    indexes: &[IndexDescription<'_>],
+    drifts: &mut Vec<Drift>,
+) {
+    for spec in specs {
+        let Some((_, key_schema, projection)) = indexes
+            .iter()
+            .find(|(index_name, ..)| *index_name == Some(spec.index_name.as_str()))
+        else {
+            drifts.push(Drift::MissingIndex {
+                table: table.to_string(),
+                index: spec.index_name.clone(),
+            });
+            continue;
+        };
+        diff_key(
+            table,
+            Some(&spec.index_name),
+            &spec.hash_key,
+            spec.range_key.as_deref(),
+            key_schema,
+            drifts,
+        );
+        let projection = projection.and_then(projection_from_sdk);
+        if !is_same_projection(&spec.projection, projection.as_ref()) {
+            drifts.push(Drift::WrongProjection {
+                table: table.to_string(),
+                index: spec.index_name.clone(),
+                expected: spec.projection.clone(),
+                actual: projection,
+            });
+        }
+    }
+    for (index_name, ..) in indexes {
+        let index_name = index_name.unwrap_or_default();
+        if !specs.iter().any(|spec| spec.index_name == index_name) {
+            drifts.push(Drift::UnexpectedIndex {
+                table: table.to_string(),
+                index: index_name.to_string(),
+            });
+        }
+    }
+}
+
+fn diff_key(
+    table: &str,
+    index: Option<&str>,
+    hash_key: &str,
+    range_key: Option<&str>,
+    key_schema: &[KeySchemaElement],
+    drifts: &mut Vec<Drift>,
+) {
+    for (key_type, expected) in [(KeyType::Hash, Some(hash_key)), (KeyType::Range, range_key)] {
+        let actual = key_schema
+            .iter()
+            .find(|element| *element.key_type() == key_type)
+            .map(|element| element.attribute_name());
+        if actual != expected {
+            drifts.push(Drift::WrongKey {
+                table: table.to_string(),
+                index: index.map(str::to_string),
+                key_type,
+                expected: expected.map(str::to_string),
+                actual: actual.map(str::to_string),
+            });
+        }
+    }
+}
+
+/// Compares projections ignoring the order of non-key attributes.
+fn is_same_projection(expected: &Projection, actual: Option<&Projection>) -> bool {
+    match (expected, actual) {
+        (
+            Projection::Include {
+                non_key_attributes: expected,
+            },
+            Some(Projection::Include {
+                non_key_attributes: actual,
+            }),
+        ) => expected.iter().collect::<BTreeSet<_>>() == actual.iter().collect::<BTreeSet<_>>(),
+        (expected, actual) => Some(expected) == actual,
+    }
+}
+
+fn attribute_type_from_sdk(attribute_type: &ScalarAttributeType) -> Option<AttributeType> {
+    match attribute_type {
+        ScalarAttributeType::S => Some(AttributeType::S),
+        ScalarAttributeType::N => Some(AttributeType::N),
+        ScalarAttributeType::B => Some(AttributeType::B),
+        _ => None,
+    }
+}
+
+fn projection_from_sdk(projection: &aws_sdk_dynamodb::types::Projection) -> Option<Projection> {
+    match projection.projection_type()? {
+        ProjectionType::All => Some(Projection::All),
+        ProjectionType::KeysOnly => Some(Projection::KeysOnly),
+        ProjectionType::Include => Some(Projection::Include {
+            non_key_attributes: projection.non_key_attributes().to_vec(),
+        }),
+        _ => None,
+    }
+}
+
+fn stream_view_type_from_sdk(
+    stream_view_type: &aws_sdk_dynamodb::types::StreamViewType,
+) -> Option<StreamViewType> {
+    use aws_sdk_dynamodb::types::StreamViewType as Sdk;
+    match stream_view_type {
+        Sdk::KeysOnly => Some(StreamViewType::KeysOnly),
+        Sdk::NewImage => Some(StreamViewType::NewImage),
+        Sdk::OldImage => Some(StreamViewType::OldImage),
+        Sdk::NewAndOldImages => Some(StreamViewType::NewAndOldImages),
+        _ => None,
+    }
+}
@@ -0,0 +1,514 @@
+pub mod cloudformation;
+pub mod drift;
+pub mod spec;
+
+pub use spec::{
+    TableSpec, TableSpecs, create_table, default_table_specs, register_table_spec,
+    register_table_specs_from_file, registered_table_specs, unregister_table_spec,
+};
+
+use crate::error::{Error, Result};
+use crate::localstack::{
+    LocalStackBuilder, LocalStackInstance, Snapshot, get_dynamodb_client_in_region,
+};
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::client::Waiters;
+use aws_sdk_dynamodb::types::{
+    DeleteRequest, IndexStatus, PutRequest, Select, TableStatus, WriteRequest,
+};
+use item_core::item_model::ItemModel;
+use serde_dynamo::aws_sdk_dynamodb_1::to_item;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Name of the container of [`localstack_builder`].
+pub const CONTAINER_NAME: &str = "localstack-test-api-dynamodb";
+
+/// Name under which this environment is recorded once provisioned in a Localstack.
+pub const ENVIRONMENT: &str = "dynamodb";
+
+static LOCALSTACK_DYNAMODB: OnceCell<LocalStackInstance> = OnceCell::const_new();
+
+/// Lazily initializes and returns a shared Localstack running DynamoDB.
+///
+/// If initialization fails, the next call tries again. The tables are provisioned via
+/// [`LocalStackInstance::provision_environment`].
+pub async fn get_localstack_dynamodb() -> Result<&'static LocalStackInstance> {
+    LOCALSTACK_DYNAMODB
+        .get_or_try_init(|| async {
+            let localstack = localstack_builder().start().await?;
+            localstack
+                .provision_environment(
+                    ENVIRONMENT,
+                    |snapshot| std::future::ready(Ok(with_fingerprint(snapshot))),
+                    || init(&localstack),
+                )
+                .await?;
+            Ok(localstack)
+        })
+        .await
+}
+
+/// Version of what [`init`] provisions besides the registered table-specs and [`ITEMS_DATA`].
+///
+/// Bump it when changing how tables are created or populated, so existing snapshots are taken again.
+const PROVISIONING_VERSION: &str = "1";
+
+/// Adds everything [`init`] provisions to the fingerprint of `snapshot`.
+pub(crate) fn with_fingerprint(snapshot: Snapshot) -> Snapshot {
+    let specs = serde_json::to_string(&registered_table_specs()).expect(
+        "shouldn't fail serializing table-specs because they only contain strings and maps",
+    );
+    snapshot
+        .with_fingerprint(PROVISIONING_VERSION)
+        .with_fingerprint(specs)
+        .with_fingerprint(ITEMS_DATA)
+}
+
+/// Describes the Localstack of [`get_localstack_dynamodb`].
+pub fn localstack_builder() -> LocalStackBuilder {
+    LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .with_container_name(CONTAINER_NAME)
+        .with_reuse_per_test_process()
+}
+
+/// Sets up all [registered tables](registered_table_specs) in the region of `localstack`.
+///
+/// Tables that already exist are left as they are, so this can run against a reused Localstack.
+pub async fn init(localstack: &LocalStackInstance) -> Result<()> {
+    init_in_regions(localstack, &[localstack.region()]).await
+}
+
+/// Sets up all [registered tables](registered_table_specs) in each of `regions`.
+pub async fn init_in_regions(localstack: &LocalStackInstance, regions: &[&str]) -> Result<()> {
+    let specs = registered_table_specs();
+    for region in regions {
+        let client = get_dynamodb_client_in_region(localstack, region);
+        for spec in &specs {
+            create_table(&client, spec).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets up all tables and populates them with test data.
+///
+/// The test data resides in `../data/`.
+pub async fn setup(client: &Client) -> Result<()> {
+    populate_tables(client).await
+}
+
+async fn populate_tables(client: &Client) -> Result<()> {
+    populate_items(client).await
+}
+
+const ITEMS_DATA: &str = include_str!("../../data/items.json");
+
+async fn populate_items(client: &Client) -> Result<()> {
+    let all_items: Vec<ItemModel> =
+        serde_json::from_str(ITEMS_DATA).map_err(|e| items_fixture_error(e.into()))?;
+
+    for items in all_items.chunks(25) {
+        let reqs = items
+            .iter()
+            .map(|item| {
+                let payload = to_item(item).map_err(|e| items_fixture_error(e.into()))?;
+                let req = PutRequest::builder()
+                    .set_item(Some(payload))
+                    .build()
+                    .expect("shouldn't fail building a put request because 'item' has been set");
+                Ok(WriteRequest::builder().set_put_request(Some(req)).build())
+            })
+            .collect::<Result<_>>()?;
+
+        write_all(client, "items", reqs).await?;
+    }
+
+    Ok(())
+}
+
+fn items_fixture_error(source: crate::error::BoxError) -> Error {
+    Error::Fixture {
+        name: "data/items.json".to_string(),
+        source,
+    }
+}
+
+/// How [`reset_with`] empties a table before repopulating it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetStrategy {
+    /// Scans the table and deletes its items in batches. Fast for few items.
+    Truncate,
+    /// Deletes the table and creates it again from its [registered spec](registered_table_specs).
+    /// Fast for many items. Tables without registered spec are truncated.
+    Recreate,
+    /// Recreates tables holding more than [`RECREATE_THRESHOLD`] items and truncates all others.
+    #[default]
+    Auto,
+}
+
+/// Number of items above which [`ResetStrategy::Auto`] recreates a table instead of truncating it.
+pub const RECREATE_THRESHOLD: i32 = 1000;
+const TABLE_ACTIVE_TIMEOUT: Duration = Duration::from_secs(30);
+const TABLE_ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Attempts of a batch-write before giving up on its unprocessed requests.
+const UNPROCESSED_ITEMS_ATTEMPTS: u32 = 8;
+const UNPROCESSED_ITEMS_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Resets the DynamoDB to it's [`initial`](setup) state using [`ResetStrategy::Auto`].
+///
+/// See [`reset_with`].
+pub async fn reset(client: &Client) -> Result<()> {
+    reset_with(client, ResetStrategy::default()).await
+}
+
+/// Resets the DynamoDB to it's [`initial`](setup) state.
+///
+/// Creates missing [registered tables](registered_table_specs), empties all tables according to
+/// `strategy` and repopulates them with test data. Returns once all tables and their global
+/// secondary indexes are `ACTIVE` and every index holds the test data, so queries on indexes see
+/// it.
+///
+/// The test data resides in `../data/`.
+pub async fn reset_with(client: &Client, strategy: ResetStrategy) -> Result<()> {
+    let specs = registered_table_specs();
+    for spec in &specs {
+        create_table(client, spec).await?;
+    }
+
+    let tables = list_tables(client).await?;
+    for table in &tables {
+        let spec = specs.iter().find(|spec| spec.table_name == *table);
+        let recreate = match (strategy, spec) {
+            (ResetStrategy::Recreate, Some(_)) => true,
+            (ResetStrategy::Auto, Some(_)) => {
+                has_more_items_than(client, table, RECREATE_THRESHOLD).await?
+            }
+            _ => false,
+        };
+        match spec {
+            Some(spec) if recreate => recreate_table(client, spec).await?,
+            _ => truncate_table(client, table).await?,
+        }
+    }
+
+    populate_tables(client).await?;
+    for table in &tables {
+        wait_until_table_active(client, table).await?;
+        wait_until_indexes_consistent(client, table).await?;
+    }
+    Ok(())
+}
+
+async fn list_tables(client: &Client) -> Result<Vec<String>, aws_sdk_dynamodb::Error> {
+    let mut tables = Vec::new();
+    let mut exclusive_start_table_name = None;
+    loop {
+        let output = client
+            .list_tables()
+            .set_exclusive_start_table_name(exclusive_start_table_name)
+            .send()
+            .await?;
+        tables.extend(output.table_names.unwrap_or_default());
+        match output.last_evaluated_table_name {
+            Some(table) => exclusive_start_table_name = Some(table),
+            None => return Ok(tables),
+        }
+    }
+}
+
+async fn has_more_items_than(
+    client: &Client,
+    table: &str,
+    threshold: i32,
+) -> Result<bool, aws_sdk_dynamodb::Error> {
+    let mut count = 0;
+    let mut exclusive_start_key = None;
+    // A page may end before the limit, because DynamoDB reads at most 1 MB per page.
+    loop {
+        let scan_output = client
+            .scan()
+            .table_name(table)
+            .select(Select::Count)
+            .limit(threshold + 1 - count)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        count += scan_output.count;
+        if count > threshold {
+            return Ok(true);
+        }
+        match scan_output.last_evaluated_key {
+            Some(last_evaluated_key) => exclusive_start_key = Some(last_evaluated_key),
+            None => return Ok(false),
+        }
+    }
+}
+
+async fn recreate_table(client: &Client, spec: &TableSpec) -> Result<()> {
+    client
+        .delete_table()
+        .table_name(&spec.table_name)
+        .send()
+        .await
+        .map_err(aws_sdk_dynamodb::Error::from)?;
+    client
+        .wait_until_table_not_exists()
+        .table_name(&spec.table_name)
+        .wait(TABLE_ACTIVE_TIMEOUT)
+        .await
+        .map_err(|e| Error::Timeout {
+            operation: format!("waiting until table '{}' is deleted", spec.table_name),
+            timeout: TABLE_ACTIVE_TIMEOUT,
+            source: e.into(),
+        })?;
+    create_table(client, spec).await?;
+    Ok(())
+}
+
+/// Waits until the table and all of its global secondary indexes are `ACTIVE`.
+async fn wait_until_table_active(client: &Client, table: &str) -> Result<()> {
+    let deadline = Instant::now() + TABLE_ACTIVE_TIMEOUT;
+    loop {
+        let description = client
+            .describe_table()
+            .table_name(table)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?
+            .table;
+        let inactive = description
+            .iter()
+            .flat_map(|description| {
+                let table_status = description
+                    .table_status()
+                    .filter(|status| **status != TableStatus::Active)
+                    .map(|status| format!("table is {status}"));
+                let index_statuses = description
+                    .global_secondary_indexes()
+                    .iter()
+                    .filter(|index| index.index_status() != Some(&IndexStatus::Active))
+                    .map(|index| {
+                        format!(
+                            "index '{}' is {:?}",
+                            index.index_name().unwrap_or_default(),
+                            index.index_status()
+                        )
+                    });
+                table_status.into_iter().chain(index_statuses)
+            })
+            .collect::<Vec<_>>();
+        if inactive.is_empty() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::Timeout {
+                operation: format!("waiting until table '{table}' is active"),
+                timeout: TABLE_ACTIVE_TIMEOUT,
+                source: inactive.join(", ").into(),
+            });
+        }
+        tokio::time::sleep(TABLE_ACTIVE_POLL_INTERVAL).await;
+    }
+}
+
+/// Waits until every global secondary index of `table` holds exactly the items of the table that
+/// have its key-attributes, because DynamoDB updates indexes asynchronously.
+async fn wait_until_indexes_consistent(client: &Client, table: &str) -> Result<()> {
+    let indexes = client
+        .describe_table()
+        .table_name(table)
+        .send()
+        .await
+        .map_err(aws_sdk_dynamodb::Error::from)?
+        .table
+        .and_then(|description| description.global_secondary_indexes)
+        .unwrap_or_default();
+    for index in &indexes {
+        let index_name = index.index_name().unwrap_or_default();
+        let key_attributes = index
+            .key_schema()
+            .iter()
+            .map(|element| element.attribute_name().to_string())
+            .collect::<Vec<_>>();
+        let expected = count_items(client, table, None, &key_attributes).await?;
+        let deadline = Instant::now() + TABLE_ACTIVE_TIMEOUT;
+        loop {
+            let indexed = count_items(client, table, Some(index_name), &[]).await?;
+            if indexed == expected {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout {
+                    operation: format!(
+                        "waiting until index '{index_name}' of table '{table}' is consistent"
+                    ),
+                    timeout: TABLE_ACTIVE_TIMEOUT,
+                    source: format!("index holds {indexed} of {expected} items").into(),
+                });
+            }
+            tokio::time::sleep(TABLE_ACTIVE_POLL_INTERVAL).await;
+        }
+    }
+    Ok(())
+}
+
+/// Counts the items of `table`, or of its `index`, that have all `attributes`.
+async fn count_items(
+    client: &Client,
+    table: &str,
+    index: Option<&str>,
+    attributes: &[String],
+) -> Result<i32> {
+    // Placeholders avoid clashes of attributes with reserved words.
+    let filter_expression = (!attributes.is_empty()).then(|| {
+        (0..attributes.len())
+            .map(|index| format!("attribute_exists(#attribute{index})"))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    });
+    let expression_attribute_names = (!attributes.is_empty()).then(|| {
+        attributes
+            .iter()
+            .enumerate()
+            .map(|(index, attribute)| (format!("#attribute{index}"), attribute.clone()))
+            .collect::<HashMap<_, _>>()
+    });
+    let mut count = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let scan_output = client
+            .scan()
+            .table_name(table)
+            .set_index_name(index.map(str::to_string))
+            .select(Select::Count)
+            .set_filter_expression(filter_expression.clone())
+            .set_expression_attribute_names(expression_attribute_names.clone())
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        count += scan_output.count;
+        match scan_output.last_evaluated_key {
+            Some(last_evaluated_key) => exclusive_start_key = Some(last_evaluated_key),
+            None => return Ok(count),
+        }
+    }
+}
+
+/// Writes `requests` to `table`, resending unprocessed ones with exponential backoff.
+///
+/// Fails with [`Error::Timeout`] if some are still unprocessed after
+/// [`UNPROCESSED_ITEMS_ATTEMPTS`].
+async fn write_all(client: &Client, table: &str, requests: Vec<WriteRequest>) -> Result<()> {
+    let mut request_items = HashMap::from([(table.to_string(), requests)]);
+    let mut backoff = UNPROCESSED_ITEMS_INITIAL_BACKOFF;
+    let mut waited = Duration::ZERO;
+    for attempt in 1..=UNPROCESSED_ITEMS_ATTEMPTS {
+        request_items = client
+            .batch_write_item()
+            .set_request_items(Some(request_items))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?
+            .unprocessed_items
+            .unwrap_or_default();
+        if request_items.is_empty() {
+            return Ok(());
+        }
+        if attempt < UNPROCESSED_ITEMS_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            waited += backoff;
+            backoff *= 2;
+        }
+    }
+    let unprocessed = request_items.values().map(Vec::len).sum::<usize>();
+    Err(Error::Timeout {
+        operation: format!("writing to table '{table}'"),
+        timeout: waited,
+        source: format!(
+            "{unprocessed} request(s) still unprocessed after {UNPROCESSED_ITEMS_ATTEMPTS} attempts"
+        )
+        .into(),
+    })
+}
+
+/// Deletes all items of `table` in batches.
+///
+/// Works for any key-schema: scans are projected to the key-attributes named in the table's
+/// `KeySchema`, so each scanned item is the key to delete.
+async fn truncate_table(client: &Client, table: &str) -> Result<()> {
+    let key_attributes = client
+        .describe_table()
+        .table_name(table)
+        .send()
+        .await
+        .map_err(aws_sdk_dynamodb::Error::from)?
+        .table
+        .map(|description| {
+            description
+                .key_schema()
+                .iter()
+                .map(|element| element.attribute_name().to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if key_attributes.is_empty() {
+        return Err(Error::UnexpectedResponse {
+            operation: format!("truncating table '{table}'"),
+            reason: "describing it lacks the key schema".to_string(),
+        });
+    }
+    // Placeholders avoid clashes of key-attributes with reserved words.
+    let projection_expression = (0..key_attributes.len())
+        .map(|index| format!("#key{index}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let expression_attribute_names = key_attributes
+        .iter()
+        .enumerate()
+        .map(|(index, attribute)| (format!("#key{index}"), attribute.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut last_evaluated_key = None;
+
+    loop {
+        let scan_output = client
+            .scan()
+            .table_name(table)
+            .projection_expression(&projection_expression)
+            .set_expression_attribute_names(Some(expression_attribute_names.clone()))
+            .set_exclusive_start_key(last_evaluated_key)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+
+        if let Some(keys) = scan_output.items {
+            for chunk in keys.chunks(25) {
+                let delete_requests: Vec<WriteRequest> = chunk
+                    .iter()
+                    .map(|key| {
+                        WriteRequest::builder()
+                            .delete_request(
+                                DeleteRequest::builder()
+                                    .set_key(Some(key.clone()))
+                                    .build()
+                                    .expect("shouldn't fail building a delete request because 'key' has been set"),
+                            )
+                            .build()
+                    })
+                    .collect();
+
+                write_all(client, table, delete_requests).await?;
+            }
+        }
+
+        match scan_output.last_evaluated_key {
+            Some(key) => last_evaluated_key = Some(key),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+//! Declarative specifications of DynamoDB-tables and the registry of tables [`init`](super::init)
+//! creates.
+//!
+//! Specifications are written in Rust or loaded from JSON- or TOML-files listing `tables`:
+//!
+//! ```toml
+//! [[tables]]
+//! table_name = "orders"
+//! attributes = { pk = "S", created_at = "N" }
+//! hash_key = "pk"
+//! range_key = "created_at"
+//! time_to_live_attribute = "expires_at"
+//! stream_view_type = "NEW_AND_OLD_IMAGES"
+//!
+//! [[tables.global_secondary_indexes]]
+//! index_name = "gsi_1_created_at"
+//! hash_key = "created_at"
+//! projection = { type = "KEYS_ONLY" }
+//! ```
+
+use crate::error::{BoxError, Error, Result};
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::operation::create_table::CreateTableError;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, GlobalSecondaryIndex, KeySchemaElement, KeyType, LocalSecondaryIndex,
+    ProjectionType, ProvisionedThroughput, ScalarAttributeType, StreamSpecification,
+    TimeToLiveSpecification,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+const DEFAULT_TABLES_DATA: &str = include_str!("../../data/tables.json");
+
+static REGISTERED_TABLE_SPECS: LazyLock<Mutex<Vec<TableSpec>>> =
+    LazyLock::new(|| Mutex::new(default_table_specs()));
+
+/// A DynamoDB-table as passed to `CreateTable`, plus its time-to-live.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSpec {
+    pub table_name: String,
+    /// Types of all attributes used as key of the table or one of its indexes.
+    pub attributes: BTreeMap<String, AttributeType>,
+    pub hash_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub global_secondary_indexes: Vec<IndexSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local_secondary_indexes: Vec<IndexSpec>,
+    #[serde(default)]
+    pub billing_mode: BillingMode,
+    /// Required for [`BillingMode::Provisioned`]. Also applies to global secondary indexes
+    /// without own throughput.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioned_throughput: Option<Throughput>,
+    #[serde(default)]
+    pub table_class: TableClass,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_to_live_attribute: Option<String>,
+    /// Enables the table's stream if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_view_type: Option<StreamViewType>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexSpec {
+    pub index_name: String,
+    pub hash_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range_key: Option<String>,
+    #[serde(default)]
+    pub projection: Projection,
+    /// Ignored for local secondary indexes, which share the throughput of their table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioned_throughput: Option<Throughput>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttributeType {
+    S,
+    N,
+    B,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Projection {
+    #[default]
+    All,
+    KeysOnly,
+    Include {
+        non_key_attributes: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BillingMode {
+    #[default]
+    PayPerRequest,
+    Provisioned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Throughput {
+    pub read_capacity_units: i64,
+    pub write_capacity_units: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TableClass {
+    #[default]
+    Standard,
+    StandardInfrequentAccess,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StreamViewType {
+    KeysOnly,
+    NewImage,
+    OldImage,
+    NewAndOldImages,
+}
+
+/// The content of a file of table-specifications.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSpecs {
+    pub tables: Vec<TableSpec>,
+}
+
+impl TableSpecs {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| spec_error("<json>", e.into()))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).map_err(|e| spec_error("<toml>", e.into()))
+    }
+
+    /// Reads the file at `path` as JSON, or as TOML if its extension is `toml`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let content = std::fs::read_to_string(path).map_err(|e| spec_error(&name, e.into()))?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            toml::from_str(&content).map_err(|e| spec_error(&name, e.into()))
+        } else {
+            serde_json::from_str(&content).map_err(|e| spec_error(&name, e.into()))
+        }
+    }
+}
+
+fn spec_error(name: &str, source: BoxError) -> Error {
+    Error::Fixture {
+        name: name.to_string(),
+        source,
+    }
+}
+
+/// The specifications of the tables `parties`, `items` and `filters` in `data/tables.json`.
+pub fn default_table_specs() -> Vec<TableSpec> {
+    TableSpecs::from_json(DEFAULT_TABLES_DATA)
+        .expect("shouldn't fail parsing 'data/tables.json' because it's covered by tests")
+        .tables
+}
+
+/// Registers `spec` to be created by [`init`](super::init), replacing a registered specification
+/// of the same table.
+///
+/// Initially, the [`default_table_specs`] are registered. Tables are only created when a
+/// Localstack is initialized, so register them before the first test uses it.
+pub fn register_table_spec(spec: TableSpec) {
+    let mut specs = REGISTERED_TABLE_SPECS
+        .lock()
+        .expect("shouldn't fail locking table-specs because registering doesn't panic");
+    match specs
+        .iter_mut()
+        .find(|registered| registered.table_name == spec.table_name)
+    {
+        Some(registered) => *registered = spec,
+        None => specs.push(spec),
+    }
+}
+
+/// Registers all tables in the file at `path`, see [`TableSpecs::from_file`].
+pub fn register_table_specs_from_file(path: impl AsRef<Path>) -> Result<()> {
+    TableSpecs::from_file(path)?
+        .tables
+        .into_iter()
+        .for_each(register_table_spec);
+    Ok(())
+}
+
+/// Removes the registered specification of the table named `table_name`, if any.
+pub fn unregister_table_spec(table_name: &str) {
+    REGISTERED_TABLE_SPECS
+        .lock()
+        .expect("shouldn't fail locking table-specs because registering doesn't panic")
+        .retain(|spec| spec.table_name != table_name);
+}
+
+pub fn registered_table_specs() -> Vec<TableSpec> {
+    REGISTERED_TABLE_SPECS
+        .lock()
+        .expect("shouldn't fail locking table-specs because registering doesn't panic")
+        .clone()
+}
+
+/// Creates the table of `spec` and enables its time-to-live.
+///
+/// An already existing table is left as it is, e.g. in a reused Localstack. Returns whether the
+/// table was created.
+pub async fn create_table(client: &Client, spec: &TableSpec) -> Result<bool> {
+    let throughput = spec
+        .provisioned_throughput
+        .map(Throughput::to_sdk)
+        .transpose()
+        .map_err(aws_sdk_dynamodb::Error::from)?;
+    let mut request = client
+        .create_table()
+        .table_name(&spec.table_name)
+        .set_key_schema(Some(
+            key_schema(&spec.hash_key, spec.range_key.as_deref())
+                .map_err(aws_sdk_dynamodb::Error::from)?,
+        ))
+        .billing_mode(spec.billing_mode.to_sdk())
+        .set_provisioned_throughput(throughput.clone())
+        .table_class(spec.table_class.to_sdk());
+    for (name, attribute_type) in &spec.attributes {
+        request = request.attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name(name)
+                .attribute_type(attribute_type.to_sdk())
+                .build()
+                .map_err(aws_sdk_dynamodb::Error::from)?,
+        );
+    }
+    for index in &spec.global_secondary_indexes {
+        let index_throughput = match index.provisioned_throughput {
+            Some(index_throughput) => Some(
+                index_throughput
+                    .to_sdk()
+                    .map_err(aws_sdk_dynamodb::Error::from)?,
+            ),
+            None => throughput.clone(),
+        };
+        request = request.global_secondary_indexes(
+            GlobalSecondaryIndex::builder()
+                .index_name(&index.index_name)
+                .set_key_schema(Some(
+                    key_schema(&index.hash_key, index.range_key.as_deref())
+                        .map_err(aws_sdk_dynamodb::Error::from)?,
+                ))
+                .projection(index.projection.to_sdk())
+                .set_provisioned_throughput(index_throughput)
+                .build()
+                .map_err(aws_sdk_dynamodb::Error::from)?,
+        );
+    }
+    for index in &spec.local_secondary_indexes {
+        request = request.local_secondary_indexes(
+            LocalSecondaryIndex::builder()
+                .index_name(&index.index_name)
+                .set_key_schema(Some(
+                    key_schema(&index.hash_key, index.range_key.as_deref())
+                        .map_err(aws_sdk_dynamodb::Error::from)?,
+                ))
+                .projection(index.projection.to_sdk())
+                .build()
+                .map_err(aws_sdk_dynamodb::Error::from)?,
+        );
+    }
+    if let Some(stream_view_type) = spec.stream_view_type {
+        request = request.stream_specification(
+            StreamSpecification::builder()
+                .stream_enabled(true)
+                .stream_view_type(stream_view_type.to_sdk())
+                .build()
+                .map_err(aws_sdk_dynamodb::Error::from)?,
+        );
+    }
+
+    match request.send().await {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(CreateTableError::is_resource_in_use_exception) =>
+        {
+            return Ok(false);
+        }
+        Err(e) => return Err(aws_sdk_dynamodb::Error::from(e).into()),
+    }
+
+    // Enabling an already enabled time-to-live fails, so this only happens for new tables.
+    if let Some(attribute_name) = &spec.time_to_live_attribute {
+        client
+            .update_time_to_live()
+            .table_name(&spec.table_name)
+            .time_to_live_specification(
+                TimeToLiveSpecification::builder()
+                    .attribute_name(attribute_name)
+                    .enabled(true)
+                    .build()
+                    .map_err(aws_sdk_dynamodb::Error::from)?,
+            )
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+    }
+    Ok(true)
+}
+
+fn key_schema(
+    hash_key: &str,
+    range_key: Option<&str>,
+) -> Result<Vec<KeySchemaElement>, aws_sdk_dynamodb::error::BuildError> {
+    let mut key_schema = vec![
+        KeySchemaElement::builder()
+            .attribute_name(hash_key)
+            .key_type(KeyType::Hash)
+            .build()?,
+    ];
+    if let Some(range_key) = range_key {
+        key_schema.push(
+            KeySchemaElement::builder()
+                .attribute_name(range_key)
+                .key_type(KeyType::Range)
+                .build()?,
+        );
+    }
+    Ok(key_schema)
+}
+
+impl AttributeType {
+    pub fn to_sdk(self) -> ScalarAttributeType {
+        match self {
+            AttributeType::S => ScalarAttributeType::S,
+            AttributeType::N => ScalarAttributeType::N,
+            AttributeType::B => ScalarAttributeType::B,
+        }
+    }
+}
+
+impl Projection {
+    pub fn to_sdk(&self) -> aws_sdk_dynamodb::types::Projection {
+        let projection = aws_sdk_dynamodb::types::Projection::builder();
+        match self {
+            Projection::All => projection.projection_type(ProjectionType::All),
+            Projection::KeysOnly => projection.projection_type(ProjectionType::KeysOnly),
+            Projection::Include { non_key_attributes } => projection
+                .projection_type(ProjectionType::Include)
+                .set_non_key_attributes(Some(non_key_attributes.clone())),
+        }
+        .build()
+    }
+}
+
+impl BillingMode {
+    pub fn to_sdk(self) -> aws_sdk_dynamodb::types::BillingMode {
+        match self {
+            BillingMode::PayPerRequest => aws_sdk_dynamodb::types::BillingMode::PayPerRequest,
+            BillingMode::Provisioned => aws_sdk_dynamodb::types::BillingMode::Provisioned,
+        }
+    }
+}
+
+impl Throughput {
+    pub fn to_sdk(self) -> Result<ProvisionedThroughput, aws_sdk_dynamodb::error::BuildError> {
+        ProvisionedThroughput::builder()
+            .read_capacity_units(self.read_capacity_units)
+            .write_capacity_units(self.write_capacity_units)
+            .build()
+    }
+}
+
+impl TableClass {
+    pub fn to_sdk(self) -> aws_sdk_dynamodb::types::TableClass {
+        match self {
+            TableClass::Standard => aws_sdk_dynamodb::types::TableClass::Standard,
+            TableClass::StandardInfrequentAccess => {
+                aws_sdk_dynamodb::types::TableClass::StandardInfrequentAccess
+            }
+        }
+    }
+}
+
+impl StreamViewType {
+    pub fn to_sdk(self) -> aws_sdk_dynamodb::types::StreamViewType {
+        match self {
+            StreamViewType::KeysOnly => aws_sdk_dynamodb::types::StreamViewType::KeysOnly,
+            StreamViewType::NewImage => aws_sdk_dynamodb::types::StreamViewType::NewImage,
+            StreamViewType::OldImage => aws_sdk_dynamodb::types::StreamViewType::OldImage,
+            StreamViewType::NewAndOldImages => {
+                aws_sdk_dynamodb::types::StreamViewType::NewAndOldImages
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::localstack::ContainerLogs;
+use std::path::PathBuf;
+use std::time::Duration;
+use testcontainers::TestcontainersError;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors that can occur while setting up, resetting or tearing down a test-environment.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error(
+        "Docker isn't reachable: {source}. Start the Docker daemon or point DOCKER_HOST at it, \
+         use an already running Localstack by setting {endpoint_env_var}, or set \
+         {skip_env_var}=1 to skip tests requiring Docker",
+        endpoint_env_var = crate::localstack::LOCALSTACK_ENDPOINT_ENV_VAR,
+        skip_env_var = crate::localstack::SKIP_WITHOUT_DOCKER_ENV_VAR
+    )]
+    DockerUnavailable { source: BoxError },
+
+    #[error("failed {operation} via the Docker-API: {source}")]
+    Docker { operation: String, source: BoxError },
+
+    #[error("failed {operation} via Localstack's internal API: {source}")]
+    Localstack { operation: String, source: BoxError },
+
+    /// A service responded successfully, but without what the request asked for.
+    #[error("failed {operation}: the response {reason}")]
+    UnexpectedResponse { operation: String, reason: String },
+
+    #[error(
+        "failed starting Localstack container after {attempts} attempt(s): {source}{}",
+        likely_causes(diagnosis)
+    )]
+    ContainerStart {
+        source: TestcontainersError,
+        attempts: u32,
+        diagnosis: Vec<String>,
+        logs: ContainerLogs,
+    },
+
+    #[error(
+        "Localstack at '{endpoint_url}' didn't become ready within {timeout:?}. \
+         Services not ready: [{}]. Last health-status: {last_status}",
+        services.join(", ")
+    )]
+    NotReady {
+        endpoint_url: String,
+        services: Vec<String>,
+        last_status: String,
+        timeout: Duration,
+        logs: ContainerLogs,
+    },
+
+    #[error(
+        "init-scripts of Localstack at '{endpoint_url}' didn't succeed within {timeout:?}. \
+         Failed: [{}]. Pending: [{}]",
+        failed.join(", "),
+        pending.join(", ")
+    )]
+    InitScripts {
+        endpoint_url: String,
+        failed: Vec<String>,
+        pending: Vec<String>,
+        timeout: Duration,
+        logs: ContainerLogs,
+    },
+
+    #[error(transparent)]
+    DynamoDb(Box<aws_sdk_dynamodb::Error>),
+
+    #[error(transparent)]
+    Sqs(Box<aws_sdk_sqs::Error>),
+
+    #[error(transparent)]
+    Lambda(Box<aws_sdk_lambda::Error>),
+
+    #[error("failed parsing fixture '{name}': {source}")]
+    Fixture { name: String, source: BoxError },
+
+    #[error("failed fetching artifact '{artifact}': {source}")]
+    ArtifactFetch { artifact: String, source: BoxError },
+
+    #[error("failed accessing state shared with other test-processes at '{}': {source}", path.display())]
+    SharedState { path: PathBuf, source: BoxError },
+
+    #[error("timed out after {timeout:?} {operation}: {source}")]
+    Timeout {
+        operation: String,
+        timeout: Duration,
+        source: BoxError,
+    },
+}
+
+impl Error {
+    /// The logs of the Localstack container that failed to start, if any were captured.
+    pub fn container_logs(&self) -> Option<&ContainerLogs> {
+        match self {
+            Error::ContainerStart { logs, .. }
+            | Error::NotReady { logs, .. }
+            | Error::InitScripts { logs, .. } => Some(logs),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn with_container_logs(self, container_logs: ContainerLogs) -> Self {
+        match self {
+            Error::ContainerStart {
+                source,
+                attempts,
+                diagnosis,
+                ..
+            } => Error::ContainerStart {
+                source,
+                attempts,
+                diagnosis,
+                logs: container_logs,
+            },
+            Error::NotReady {
+                endpoint_url,
+                services,
+                last_status,
+                timeout,
+                ..
+            } => Error::NotReady {
+                endpoint_url,
+                services,
+                last_status,
+                timeout,
+                logs: container_logs,
+            },
+            Error::InitScripts {
+                endpoint_url,
+                failed,
+                pending,
+                timeout,
+                ..
+            } => Error::InitScripts {
+                endpoint_url,
+                failed,
+                pending,
+                timeout,
+                logs: container_logs,
+            },
+            e => e,
+        }
+    }
+
+    /// Whether a test should be skipped instead of failing because of this error.
+    ///
+    /// This is the case if Docker is unavailable and
+    /// [`SKIP_WITHOUT_DOCKER_ENV_VAR`](crate::localstack::SKIP_WITHOUT_DOCKER_ENV_VAR) is set.
+    pub fn should_skip_test(&self) -> bool {
+        matches!(self, Error::DockerUnavailable { .. })
+            && crate::localstack::is_env_flag_set(crate::localstack::SKIP_WITHOUT_DOCKER_ENV_VAR)
+    }
+
+    /// Dumps the captured [`container_logs`](Error::container_logs) for `test_name` and panics.
+    ///
+    /// Used by the test-macros when setting up the test-environment fails.
+    pub fn dump_logs_and_panic(self, test_name: &str, msg: &str) -> ! {
+        if let Some(logs) = self.container_logs().filter(|logs| !logs.is_empty()) {
+            logs.dump(test_name);
+        }
+        panic!("{msg}: {self}")
+    }
+}
+
+fn likely_causes(diagnosis: &[String]) -> String {
+    if diagnosis.is_empty() {
+        String::new()
+    } else {
+        format!(". Likely causes: {}", diagnosis.join("; "))
+    }
+}
+
+// The SDK-errors are boxed to keep `Result<_, Error>` small.
+impl From<aws_sdk_dynamodb::Error> for Error {
+    fn from(e: aws_sdk_dynamodb::Error) -> Self {
+        Error::DynamoDb(Box::new(e))
+    }
+}
+
+impl From<aws_sdk_sqs::Error> for Error {
+    fn from(e: aws_sdk_sqs::Error) -> Self {
+        Error::Sqs(Box::new(e))
+    }
+}
+
+impl From<aws_sdk_lambda::Error> for Error {
+    fn from(e: aws_sdk_lambda::Error) -> Self {
+        Error::Lambda(Box::new(e))
+    }
+}
@@ -1,7 +1,11 @@
+pub mod artifact;
 pub mod dynamodb;
+pub mod error;
 pub mod generator;
 pub mod localstack;
+pub mod logging;
 pub mod sqs_lambda_dynamodb;
 
+pub use error::{Error, Result};
 pub use serial_test;
 pub use test_api_macros;
@@ -1,95 +1,1519 @@
+mod docker;
+mod shared;
+mod snapshot;
+
+pub use shared::ExclusiveUse;
+pub use snapshot::{SNAPSHOT_DIR_ENV_VAR, Snapshot};
+
+use crate::error::{Error, Result};
 use aws_config::{BehaviorVersion, SdkConfig};
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::config::Credentials;
-use std::collections::HashMap;
-use std::process::Command;
-use testcontainers::core::{IntoContainerPort, Mount};
+use bollard::Docker;
+use bollard::container::{ListContainersOptions, LogOutput, LogsOptions};
+use docker::{cleanup_existing_container, docker_error, is_not_found, tear_down};
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use shared::{Lease, SharedState, StateLock};
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::future::Future;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once, TryLockError};
+use std::time::{Duration, Instant};
+use testcontainers::core::logs::LogFrame;
+use testcontainers::core::{AccessMode, ContainerPort, Mount};
 use testcontainers::runners::AsyncRunner;
-use testcontainers::{ContainerAsync, ImageExt};
+use testcontainers::{ContainerAsync, ImageExt, TestcontainersError};
 use testcontainers_modules::localstack::LocalStack;
-use tokio::sync::OnceCell;
 
+pub const LOCALSTACK_IMAGE: &str = "localstack/localstack";
 pub const LOCALSTACK_CONTAINER_NAME: &str = "localstack-test-api";
+pub const LOCALSTACK_DEFAULT_TAG: &str = "latest";
+pub const LOCALSTACK_DEFAULT_REGION: &str = "eu-central-1";
+pub const LOCALSTACK_DEFAULT_ACCOUNT_ID: &str = "000000000000";
 
-pub async fn spin_up_localstack(env_vars: HashMap<&str, &str>) -> ContainerAsync<LocalStack> {
-    let _ = tracing_subscriber::fmt()
-        .json()
-        .with_max_level(tracing::Level::INFO)
-        .with_current_span(true)
-        .with_ansi(false)
-        .try_init();
-
-    cleanup_existing_container(LOCALSTACK_CONTAINER_NAME);
-    let request = env_vars
-        .iter()
-        .fold(
-            LocalStack::default()
-                .with_tag("latest")
-                .with_container_name(LOCALSTACK_CONTAINER_NAME),
-            |ls, (k, v)| ls.with_env_var(*k, *v),
-        )
-        .with_mount(Mount::bind_mount("/var/run/docker.sock", "/var/run/docker.sock"))
-        .with_mapped_port(4566, 4566.tcp());
-
-    request
-        .start()
-        .await
-        .map_err(|e| {
-            eprintln!("Failed to start LocalStack: {e:?}");
-            e
-        })
-        .unwrap()
-}
+/// Overrides [`LOCALSTACK_DEFAULT_REGION`] for every [`LocalStackBuilder`].
+pub const REGION_ENV_VAR: &str = "TEST_API_REGION";
+/// Overrides [`LOCALSTACK_DEFAULT_ACCOUNT_ID`] for every [`LocalStackBuilder`].
+pub const ACCOUNT_ID_ENV_VAR: &str = "TEST_API_ACCOUNT_ID";
+
+/// The port Localstack listens on inside the container.
+pub const LOCALSTACK_PORT: u16 = 4566;
+
+/// If set, no container is started. Instead, the Localstack already running at this URL is used,
+/// e.g. a service-container in CI.
+pub const LOCALSTACK_ENDPOINT_ENV_VAR: &str = "TEST_API_LOCALSTACK_ENDPOINT";
+
+/// If set to `1` or `true`, containers are kept running after the tests, e.g. for inspecting
+/// their state. See [`LocalStackBuilder::with_keep_alive`].
+///
+/// Reused containers are kept running regardless, which includes the containers of
+/// [`get_localstack_dynamodb`](crate::dynamodb::get_localstack_dynamodb) and
+/// [`get_localstack_sqs_lambda_dynamodb`](crate::sqs_lambda_dynamodb::get_localstack_sqs_lambda_dynamodb)
+/// under cargo-nextest. Remove them via `docker rm -f` once done.
+pub const KEEP_CONTAINER_ENV_VAR: &str = "TEST_API_KEEP_CONTAINER";
+
+/// If set to `1` or `true`, a running compatible container is reused instead of starting a new
+/// one. See [`LocalStackBuilder::with_reuse`].
+pub const REUSE_CONTAINER_ENV_VAR: &str = "TEST_API_REUSE_CONTAINER";
+
+/// If set to `1` or `true`, tests built on the test-macros are skipped instead of failing when
+/// Docker is unavailable. See [`Error::should_skip_test`].
+pub const SKIP_WITHOUT_DOCKER_ENV_VAR: &str = "TEST_API_SKIP_WITHOUT_DOCKER";
 
-pub async fn spin_up_localstack_with_services(services: &[&str]) -> ContainerAsync<LocalStack> {
-    spin_up_localstack(HashMap::from([("SERVICES", services.join(",").as_str())])).await
+/// Label holding the hash of the configuration a container was started with.
+pub const CONFIG_HASH_LABEL: &str = "test-api.config-hash";
+
+/// The directory Localstack runs init-scripts from once all services are ready.
+pub const INIT_READY_DIR: &str = "/etc/localstack/init/ready.d";
+/// The directory Localstack persists its state in, see [`LocalStackBuilder::with_persistence`].
+pub const PERSISTENCE_DIR: &str = "/var/lib/localstack";
+
+pub const DEFAULT_STARTUP_ATTEMPTS: u32 = 3;
+pub const DEFAULT_STARTUP_BACKOFF: Duration = Duration::from_secs(2);
+
+pub const DEFAULT_READINESS_TIMEOUT: Duration = Duration::from_secs(60);
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(600);
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Declarative configuration of a Localstack container.
+///
+/// Every setting has a default, so `LocalStackBuilder::new().start()` spins up a Localstack
+/// running all services with the docker-socket mounted (required for Lambda).
+///
+/// If an external endpoint is configured, either via [`LocalStackBuilder::with_external_endpoint`]
+/// or [`LOCALSTACK_ENDPOINT_ENV_VAR`], no container is started and all container-settings are
+/// ignored.
+///
+/// ```no_run
+/// # async fn example() -> test_api::Result<()> {
+/// use test_api::localstack::LocalStackBuilder;
+///
+/// let localstack = LocalStackBuilder::new()
+///     .with_services(["sqs", "dynamodb"])
+///     .with_tag("4.3")
+///     .with_region("us-east-1")
+///     .start()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LocalStackBuilder {
+    services: Vec<String>,
+    tag: String,
+    region: String,
+    account_id: String,
+    container_name: String,
+    mounts: Vec<Mount>,
+    env_vars: BTreeMap<String, String>,
+    startup_timeout: Option<Duration>,
+    startup_attempts: u32,
+    startup_backoff: Duration,
+    host_port: Option<u16>,
+    init_scripts_dirs: Vec<PathBuf>,
+    init_scripts: BTreeMap<String, String>,
+    persistence_dir: Option<PathBuf>,
+    readiness_timeout: Duration,
+    external_endpoint: Option<String>,
+    keep_alive: bool,
+    reuse: bool,
+    queue_timeout: Duration,
 }
 
-fn cleanup_existing_container(name: &str) {
-    let _ = Command::new("docker").args(["rm", "-f", name]).output();
+impl Default for LocalStackBuilder {
+    fn default() -> Self {
+        Self {
+            services: Vec::new(),
+            tag: LOCALSTACK_DEFAULT_TAG.to_string(),
+            region: std::env::var(REGION_ENV_VAR)
+                .unwrap_or_else(|_| LOCALSTACK_DEFAULT_REGION.to_string()),
+            account_id: std::env::var(ACCOUNT_ID_ENV_VAR)
+                .unwrap_or_else(|_| LOCALSTACK_DEFAULT_ACCOUNT_ID.to_string()),
+            container_name: LOCALSTACK_CONTAINER_NAME.to_string(),
+            mounts: vec![Mount::bind_mount(
+                "/var/run/docker.sock",
+                "/var/run/docker.sock",
+            )],
+            env_vars: BTreeMap::new(),
+            startup_timeout: None,
+            startup_attempts: DEFAULT_STARTUP_ATTEMPTS,
+            startup_backoff: DEFAULT_STARTUP_BACKOFF,
+            host_port: None,
+            init_scripts_dirs: Vec::new(),
+            init_scripts: BTreeMap::new(),
+            persistence_dir: None,
+            readiness_timeout: DEFAULT_READINESS_TIMEOUT,
+            external_endpoint: std::env::var(LOCALSTACK_ENDPOINT_ENV_VAR).ok(),
+            keep_alive: is_env_flag_set(KEEP_CONTAINER_ENV_VAR),
+            reuse: is_env_flag_set(REUSE_CONTAINER_ENV_VAR),
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+        }
+    }
 }
 
-static CONFIG: OnceCell<SdkConfig> = OnceCell::const_new();
-/// Lazily initializes and returns a shared AWS-Config.
-pub async fn get_aws_config() -> &'static SdkConfig {
-    CONFIG
-        .get_or_init(|| async {
-            aws_config::defaults(BehaviorVersion::latest())
-                .credentials_provider(Credentials::for_tests())
-                .region("eu-central-1")
-                .endpoint_url("http://localhost:4566")
-                .load()
+impl LocalStackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds services to enable. If none are given, Localstack enables all of them.
+    pub fn with_services<S: Into<String>>(mut self, services: impl IntoIterator<Item = S>) -> Self {
+        self.services.extend(services.into_iter().map(Into::into));
+        self
+    }
+
+    /// Pins the image-tag of `localstack/localstack`. Defaults to [`LOCALSTACK_DEFAULT_TAG`].
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = tag.into();
+        self
+    }
+
+    /// Sets the region of the [`SdkConfig`].
+    ///
+    /// Defaults to [`REGION_ENV_VAR`] if set, otherwise [`LOCALSTACK_DEFAULT_REGION`].
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    /// Sets the 12-digit account-id all resources are created in.
+    ///
+    /// Defaults to [`ACCOUNT_ID_ENV_VAR`] if set, otherwise [`LOCALSTACK_DEFAULT_ACCOUNT_ID`].
+    pub fn with_account_id(mut self, account_id: impl Into<String>) -> Self {
+        self.account_id = account_id.into();
+        self
+    }
+
+    /// Adds a mount in addition to the docker-socket.
+    pub fn with_mount(mut self, mount: Mount) -> Self {
+        self.mounts.push(mount);
+        self
+    }
+
+    /// Sets an environment variable of the container, overriding previous values for `key`.
+    pub fn with_env_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env_vars.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets how long to wait for Localstack to report readiness.
+    pub fn with_startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how often starting the container is attempted before giving up, e.g. because pulling
+    /// the image hiccuped. Defaults to [`DEFAULT_STARTUP_ATTEMPTS`].
+    pub fn with_startup_attempts(mut self, attempts: u32) -> Self {
+        self.startup_attempts = attempts.max(1);
+        self
+    }
+
+    /// Sets how long to wait before the second attempt of starting the container. The wait doubles
+    /// with every further attempt. Defaults to [`DEFAULT_STARTUP_BACKOFF`].
+    pub fn with_startup_backoff(mut self, backoff: Duration) -> Self {
+        self.startup_backoff = backoff;
+        self
+    }
+
+    /// Binds Localstack to a fixed port of the host instead of a random one.
+    pub fn with_host_port(mut self, port: u16) -> Self {
+        self.host_port = Some(port);
+        self
+    }
+
+    /// Adds the scripts in `dir` to run once all services are ready, e.g. to provision resources.
+    ///
+    /// Localstack runs scripts in alphabetical order. [`LocalStackBuilder::start`] waits until all
+    /// of them completed successfully.
+    pub fn with_init_scripts_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.init_scripts_dirs.push(dir.into());
+        self
+    }
+
+    /// Adds a script named `name` to run once all services are ready, overriding a script of the
+    /// same name in [`LocalStackBuilder::with_init_scripts_dir`].
+    ///
+    /// ```no_run
+    /// # async fn example() -> test_api::Result<()> {
+    /// use test_api::localstack::LocalStackBuilder;
+    ///
+    /// let localstack = LocalStackBuilder::new()
+    ///     .with_services(["s3"])
+    ///     .with_init_script("create-bucket.sh", "awslocal s3 mb s3://raw-pages")
+    ///     .start()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_init_script(mut self, name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.init_scripts.insert(name.into(), content.into());
+        self
+    }
+
+    /// Persists the state of Localstack in `dir` on the host, so a new container starts with the
+    /// resources of the previous one. The directory is created if missing.
+    ///
+    /// Requires Localstack Pro. Containers persisting in different directories aren't reused for
+    /// each other.
+    pub fn with_persistence(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.persistence_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets how long to wait for all services to report readiness after Localstack started.
+    /// Defaults to [`DEFAULT_READINESS_TIMEOUT`].
+    pub fn with_readiness_timeout(mut self, timeout: Duration) -> Self {
+        self.readiness_timeout = timeout;
+        self
+    }
+
+    /// Sets the container-name. Defaults to [`LOCALSTACK_CONTAINER_NAME`].
+    ///
+    /// Processes, e.g. concurrently running test-binaries, coordinate on this name: While a
+    /// container with the same configuration is used by another process, it is shared instead of
+    /// started. While it's used with a different configuration, starting waits until it's
+    /// released, see [`LocalStackBuilder::with_queue_timeout`]. Otherwise, any existing container
+    /// with this name is removed before starting.
+    ///
+    /// Builders of different services therefore need different names, otherwise their containers
+    /// replace each other. With an external endpoint, processes coordinate on the endpoint instead.
+    pub fn with_container_name(mut self, name: impl Into<String>) -> Self {
+        self.container_name = name.into();
+        self
+    }
+
+    /// Uses the Localstack already running at `endpoint_url` instead of starting a container.
+    ///
+    /// Defaults to the value of [`LOCALSTACK_ENDPOINT_ENV_VAR`].
+    pub fn with_external_endpoint(mut self, endpoint_url: impl Into<String>) -> Self {
+        self.external_endpoint = Some(endpoint_url.into());
+        self
+    }
+
+    /// Keeps the container running after the [`LocalStackInstance`] is dropped and the test-binary
+    /// exited, e.g. for inspecting its state.
+    ///
+    /// Defaults to [`KEEP_CONTAINER_ENV_VAR`]. The container is still removed by the next
+    /// [`LocalStackBuilder::start`] using the same container-name.
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Reuses a running container with the same name and configuration instead of starting a new
+    /// one, e.g. one kept alive by a previous test-run.
+    ///
+    /// Containers started in this mode are kept alive, so the next run can reuse them. A running
+    /// container of a different configuration (image-tag, services, environment, mounts) is
+    /// replaced. Logs of a reused container aren't captured.
+    ///
+    /// Defaults to [`REUSE_CONTAINER_ENV_VAR`].
+    pub fn with_reuse(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    /// Enables [reuse](LocalStackBuilder::with_reuse) under cargo-nextest, which runs every test in
+    /// its own process. The first process then starts and provisions the container, and the
+    /// following ones attach to it, see [`LocalStackInstance::provision`].
+    ///
+    /// Only the builders of this crate's environments do this, as they have fixed container-names,
+    /// so each leaves one container running after the tests, which the next run reuses.
+    pub(crate) fn with_reuse_per_test_process(mut self) -> Self {
+        self.reuse |= is_process_per_test();
+        self
+    }
+
+    /// Sets how long to wait for other processes to release a container of the same name but a
+    /// different configuration. Defaults to [`DEFAULT_QUEUE_TIMEOUT`].
+    pub fn with_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = timeout;
+        self
+    }
+
+    /// Starts the container and builds an AWS-Config pointing at it.
+    ///
+    /// If an external endpoint is configured, only verifies that it is healthy. The same applies to
+    /// a container shared with another process (see [`LocalStackBuilder::with_container_name`])
+    /// and a running container found in reuse-mode (see [`LocalStackBuilder::with_reuse`]).
+    ///
+    /// In all cases, this waits until every configured service reports to be `available` or
+    /// `running` on Localstack's health-endpoint.
+    pub async fn start(mut self) -> Result<LocalStackInstance> {
+        if self.account_id.len() != 12 || !self.account_id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(Error::Config(format!(
+                "account-id must consist of 12 digits but is '{}'",
+                self.account_id
+            )));
+        }
+
+        match self.external_endpoint.clone() {
+            Some(endpoint_url) => {
+                let endpoint_url = endpoint_url.trim_end_matches('/').to_string();
+                wait_until_ready(&endpoint_url, &self.services, self.readiness_timeout).await?;
+                Ok(self.into_instance(None, endpoint_url, None).await)
+            }
+            None => {
+                self.keep_alive |= self.reuse;
+                self.resolve_init_scripts()?;
+                self.resolve_persistence()?;
+                self.start_shared().await
+            }
+        }
+    }
+
+    /// Reads the scripts of all init-script directories into the inline init-scripts, so they're
+    /// part of the [`config_hash`](Self::config_hash).
+    fn resolve_init_scripts(&mut self) -> Result<()> {
+        let mut scripts = BTreeMap::new();
+        for dir in &self.init_scripts_dirs {
+            let init_scripts_error = |e: std::io::Error| {
+                Error::Config(format!(
+                    "failed reading init-scripts from '{}': {e}",
+                    dir.display()
+                ))
+            };
+            for entry in std::fs::read_dir(dir).map_err(init_scripts_error)? {
+                let path = entry.map_err(init_scripts_error)?.path();
+                if path.is_file() {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    let content = std::fs::read_to_string(&path).map_err(init_scripts_error)?;
+                    scripts.insert(name.into_owned(), content);
+                }
+            }
+        }
+        scripts.append(&mut self.init_scripts);
+        self.init_scripts = scripts;
+        Ok(())
+    }
+
+    /// Mounts the persistence-directory, if any, and enables Localstack's persistence.
+    fn resolve_persistence(&mut self) -> Result<()> {
+        let Some(dir) = &self.persistence_dir else {
+            return Ok(());
+        };
+        // Bind-mounts require an absolute path.
+        let dir = std::fs::create_dir_all(dir)
+            .and_then(|_| dir.canonicalize())
+            .map_err(|e| {
+                Error::Config(format!(
+                    "failed creating persistence-directory '{}': {e}",
+                    dir.display()
+                ))
+            })?;
+        self.env_vars
+            .insert("PERSISTENCE".to_string(), "1".to_string());
+        self.mounts
+            .push(Mount::bind_mount(dir.to_string_lossy(), PERSISTENCE_DIR));
+        Ok(())
+    }
+
+    /// Attaches to the container of another process using the same configuration, or starts it.
+    async fn start_shared(self) -> Result<LocalStackInstance> {
+        // Checked up front, because testcontainers only fails deep inside with an opaque error.
+        let docker = docker::client()?;
+        docker
+            .ping()
+            .await
+            .map_err(|e| Error::DockerUnavailable { source: e.into() })?;
+        let config_hash = self.config_hash();
+        let deadline = Instant::now() + self.queue_timeout;
+        loop {
+            let mut lock = StateLock::acquire(&self.container_name).await?;
+            let state = lock.read()?;
+            if state.holders.is_empty() {
+                // Holding the lock makes other processes wait until the container is started.
+                return self
+                    .start_container(&docker, lock, state, config_hash)
+                    .await;
+            }
+            if state.config_hash == config_hash {
+                let endpoint_url = state.endpoint_url.clone();
+                wait_until_ready(&endpoint_url, &self.services, self.readiness_timeout).await?;
+                let lease =
+                    Lease::acquire(&mut lock, state, &self.container_name, self.keep_alive)?;
+                return Ok(self.into_instance(None, endpoint_url, Some(lease)).await);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout {
+                    operation: format!(
+                        "waiting for container '{}' to be released",
+                        self.container_name
+                    ),
+                    timeout: self.queue_timeout,
+                    source: format!(
+                        "it's still used with a different configuration by processes {:?}",
+                        state.holders
+                    )
+                    .into(),
+                });
+            }
+            drop(lock);
+            tokio::time::sleep(QUEUE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Starts the container once and returns it together with its endpoint.
+    async fn run_container(
+        &self,
+        config_hash: &str,
+        init_scripts_dir: Option<&Path>,
+        logs: &ContainerLogs,
+    ) -> Result<(ContainerAsync<LocalStack>, String), TestcontainersError> {
+        let log_consumer = logs.clone();
+        let mut request = LocalStack::default()
+            .with_tag(&self.tag)
+            .with_container_name(&self.container_name)
+            // Localstack prefixes the containers it spawns for Lambdas with this name.
+            .with_env_var("MAIN_CONTAINER_NAME", &self.container_name)
+            .with_label(CONFIG_HASH_LABEL, config_hash)
+            .with_log_consumer(move |frame: &LogFrame| log_consumer.push(frame));
+        for (key, value) in &self.env_vars {
+            request = request.with_env_var(key, value);
+        }
+        if !self.services.is_empty() {
+            request = request.with_env_var("SERVICES", self.services.join(","));
+        }
+        for mount in &self.mounts {
+            request = request.with_mount(mount.clone());
+        }
+        if let Some(timeout) = self.startup_timeout {
+            request = request.with_startup_timeout(timeout);
+        }
+        if let Some(dir) = init_scripts_dir {
+            request = request.with_mount(
+                Mount::bind_mount(dir.to_string_lossy(), INIT_READY_DIR)
+                    .with_access_mode(AccessMode::ReadOnly),
+            );
+        }
+        if let Some(host_port) = self.host_port {
+            request = request.with_mapped_port(host_port, ContainerPort::Tcp(LOCALSTACK_PORT));
+        }
+
+        let container = request.start().await?;
+        let endpoint_url = get_endpoint_url(&container).await?;
+        Ok((container, endpoint_url))
+    }
+
+    /// Writes the init-scripts as executables to a directory in the temp-dir to mount, if any.
+    ///
+    /// The directory is only written while holding the lock on the shared state, so processes
+    /// using the same container-name don't interfere.
+    fn stage_init_scripts(&self) -> Result<Option<PathBuf>> {
+        if self.init_scripts.is_empty() {
+            return Ok(None);
+        }
+        let dir = std::env::temp_dir().join(format!("test-api-{}.ready.d", self.container_name));
+        let init_scripts_error = |e: std::io::Error| {
+            Error::Config(format!(
+                "failed staging init-scripts in '{}': {e}",
+                dir.display()
+            ))
+        };
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).map_err(init_scripts_error)?;
+        }
+        std::fs::create_dir_all(&dir).map_err(init_scripts_error)?;
+        for (name, content) in &self.init_scripts {
+            let path = dir.join(name);
+            std::fs::write(&path, content).map_err(init_scripts_error)?;
+            // Localstack only runs executable init-scripts. Other platforms have no file-modes, so
+            // their bind-mounts expose files as executable anyway.
+            #[cfg(unix)]
+            std::fs::set_permissions(&path, Permissions::from_mode(0o755))
+                .map_err(init_scripts_error)?;
+        }
+        Ok(Some(dir))
+    }
+
+    /// Looks for common causes of a failed start.
+    async fn diagnose(&self, docker: &Docker) -> Vec<String> {
+        if let Err(e) = docker.ping().await {
+            return vec![format!("the Docker daemon is unreachable: {e}")];
+        }
+
+        let mut causes = Vec::new();
+        let image = format!("{LOCALSTACK_IMAGE}:{}", self.tag);
+        if let Err(e) = docker.inspect_image(&image).await
+            && is_not_found(&e)
+        {
+            causes.push(format!(
+                "the image '{image}' isn't available locally, so pulling it failed"
+            ));
+        }
+        if let Some(host_port) = self.host_port {
+            match find_container_bound_to(docker, host_port).await {
+                Some(name) => causes.push(format!(
+                    "host-port {host_port} is already bound by container '{name}'"
+                )),
+                None if TcpListener::bind(("0.0.0.0", host_port)).is_err() => causes.push(format!(
+                    "host-port {host_port} is already bound by a process outside of Docker"
+                )),
+                None => {}
+            }
+        }
+        causes
+    }
+
+    /// Returns the state of a running and ready container matching this configuration.
+    ///
+    /// Prefers the container recorded in `state`, which was kept alive by a previous process, as it
+    /// also knows the environments provisioned in it.
+    async fn find_reusable_container(
+        &self,
+        docker: &Docker,
+        state: SharedState,
+        config_hash: &str,
+    ) -> Result<Option<SharedState>> {
+        if state.config_hash == config_hash
+            && get_health(&state.endpoint_url).await.is_ok()
+            && wait_until_ready(&state.endpoint_url, &self.services, self.readiness_timeout)
                 .await
+                .is_ok()
+        {
+            return Ok(Some(state));
+        }
+
+        let filters = HashMap::from([
+            (
+                "name".to_string(),
+                vec![format!("^{}$", self.container_name)],
+            ),
+            (
+                "label".to_string(),
+                vec![format!("{CONFIG_HASH_LABEL}={config_hash}")],
+            ),
+        ]);
+        let containers = docker
+            .list_containers(Some(ListContainersOptions {
+                filters,
+                ..Default::default()
+            }))
+            .await
+            .map_err(|e| docker_error("listing containers", e))?;
+        let Some((id, port)) = containers.into_iter().find_map(|container| {
+            let port = container
+                .ports?
+                .into_iter()
+                .find(|port| port.private_port == LOCALSTACK_PORT)?
+                .public_port?;
+            Some((container.id?, port))
+        }) else {
+            return Ok(None);
+        };
+        let endpoint_url = format!("http://{}:{port}", docker::hostname(docker).await);
+        if wait_until_ready(&endpoint_url, &self.services, self.readiness_timeout)
+            .await
+            .is_err()
+        {
+            return Ok(None);
+        }
+        Ok(Some(SharedState {
+            config_hash: config_hash.to_string(),
+            container_id: id,
+            endpoint_url,
+            ..SharedState::default()
+        }))
+    }
+
+    /// Hashes everything that makes a container (in)compatible for reuse.
+    ///
+    /// Region and account-id are excluded because they only affect the clients. The hash is stable
+    /// across builds, so containers kept alive by other builds of the tests are reused, too.
+    fn config_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hash_part(&mut hasher, &self.tag);
+        hash_part(&mut hasher, (self.services.len() as u64).to_le_bytes());
+        for service in &self.services {
+            hash_part(&mut hasher, service);
+        }
+        hash_part(&mut hasher, (self.env_vars.len() as u64).to_le_bytes());
+        for (name, value) in &self.env_vars {
+            hash_part(&mut hasher, name);
+            hash_part(&mut hasher, value);
+        }
+        hash_part(&mut hasher, (self.mounts.len() as u64).to_le_bytes());
+        for mount in &self.mounts {
+            hash_part(&mut hasher, mount.mount_type().to_string());
+            hash_part(&mut hasher, mount.source().unwrap_or_default());
+            hash_part(&mut hasher, mount.target().unwrap_or_default());
+            hash_part(&mut hasher, mount.access_mode().to_string());
+        }
+        hash_part(
+            &mut hasher,
+            self.host_port
+                .map(|port| port.to_string())
+                .unwrap_or_default(),
+        );
+        hash_part(&mut hasher, (self.init_scripts.len() as u64).to_le_bytes());
+        for (name, content) in &self.init_scripts {
+            hash_part(&mut hasher, name);
+            hash_part(&mut hasher, content);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    async fn start_container(
+        self,
+        docker: &Docker,
+        mut lock: StateLock,
+        state: SharedState,
+        config_hash: String,
+    ) -> Result<LocalStackInstance> {
+        if self.reuse
+            && let Some(state) = self
+                .find_reusable_container(docker, state, &config_hash)
+                .await?
+        {
+            let endpoint_url = state.endpoint_url.clone();
+            let lease = Lease::acquire(&mut lock, state, &self.container_name, self.keep_alive)?;
+            return Ok(self.into_instance(None, endpoint_url, Some(lease)).await);
+        }
+
+        cleanup_existing_container(docker, &self.container_name).await?;
+        let init_scripts_dir = self.stage_init_scripts()?;
+        let started_at = Instant::now();
+        let mut backoff = self.startup_backoff;
+        let mut attempt = 1;
+        let (container, endpoint_url, logs) = loop {
+            let logs = ContainerLogs::default();
+            match self
+                .run_container(&config_hash, init_scripts_dir.as_deref(), &logs)
+                .await
+            {
+                Ok((container, endpoint_url)) => break (container, endpoint_url, logs),
+                Err(source) => {
+                    let diagnosis = self.diagnose(docker).await;
+                    if attempt >= self.startup_attempts {
+                        return Err(Error::ContainerStart {
+                            source,
+                            attempts: attempt,
+                            diagnosis,
+                            logs,
+                        });
+                    }
+                    tracing::warn!(
+                        container = self.container_name,
+                        attempt,
+                        ?diagnosis,
+                        ?backoff,
+                        "Failed starting Localstack container, retrying: {source}"
+                    );
+                    cleanup_existing_container(docker, &self.container_name).await?;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        };
+        tracing::info!(
+            container = self.container_name,
+            attempts = attempt,
+            elapsed = ?started_at.elapsed(),
+            "Started Localstack container"
+        );
+
+        let ready_at = Instant::now();
+        wait_until_ready(&endpoint_url, &self.services, self.readiness_timeout)
+            .await
+            .map_err(|e| e.with_container_logs(logs.clone()))?;
+        tracing::info!(
+            container = self.container_name,
+            elapsed = ?ready_at.elapsed(),
+            "Localstack services are ready"
+        );
+        if !self.init_scripts.is_empty() {
+            let init_at = Instant::now();
+            wait_until_init_scripts_completed(&endpoint_url, self.readiness_timeout)
+                .await
+                .map_err(|e| e.with_container_logs(logs.clone()))?;
+            tracing::info!(
+                container = self.container_name,
+                elapsed = ?init_at.elapsed(),
+                "Localstack init-scripts completed"
+            );
+        }
+        let state = SharedState {
+            config_hash,
+            container_id: container.id().to_string(),
+            endpoint_url: endpoint_url.clone(),
+            ..SharedState::default()
+        };
+        let lease = Lease::acquire(&mut lock, state, &self.container_name, self.keep_alive)?;
+        Ok(self
+            .into_instance(Some(container), endpoint_url, Some(lease))
+            .await)
+    }
+
+    async fn into_instance(
+        self,
+        container: Option<ContainerAsync<LocalStack>>,
+        endpoint_url: String,
+        lease: Option<Lease>,
+    ) -> LocalStackInstance {
+        if let Some(lease) = &lease {
+            register_for_teardown_at_exit(lease.clone());
+        }
+        // Localstack uses a 12-digit access-key-id as account-id.
+        let credentials = Credentials::new(&self.account_id, "test", None, None, "test-api");
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .credentials_provider(credentials)
+            .region(aws_config::Region::new(self.region.clone()))
+            .endpoint_url(&endpoint_url)
+            .load()
+            .await;
+
+        LocalStackInstance {
+            container,
+            lock_name: match self.external_endpoint {
+                Some(_) => external_lock_name(&endpoint_url),
+                None => self.container_name,
+            },
+            endpoint_url,
+            region: self.region,
+            account_id: self.account_id,
+            config,
+            clients: Mutex::default(),
+            lease,
+        }
+    }
+}
+
+/// A running Localstack together with an AWS-Config pointing at it.
+///
+/// If the Localstack was started by [`LocalStackBuilder::start`], its container and the
+/// Lambda-containers it spawned are removed when the last process using it drops this, unless it's
+/// kept alive. Instances that are never dropped, e.g. those held in a `static`, are released when
+/// the test-binary exits.
+#[derive(Debug)]
+pub struct LocalStackInstance {
+    container: Option<ContainerAsync<LocalStack>>,
+    /// Name processes coordinate on: the container-name, or the endpoint of an external Localstack.
+    lock_name: String,
+    endpoint_url: String,
+    region: String,
+    account_id: String,
+    config: SdkConfig,
+    clients: Mutex<HashMap<(TypeId, String), Box<dyn Any + Send + Sync>>>,
+    lease: Option<Lease>,
+}
+
+impl LocalStackInstance {
+    /// The container running Localstack, or `None` if an external endpoint is used.
+    pub fn container(&self) -> Option<&ContainerAsync<LocalStack>> {
+        self.container.as_ref()
+    }
+
+    /// Fetches what the container wrote to stdout and stderr so far from the Docker-API, up to the
+    /// last [`MAX_CONTAINER_LOG_FRAMES`] frames.
+    ///
+    /// Covers containers started by other processes, too. Empty if an external endpoint is used.
+    pub async fn logs(&self) -> Result<ContainerLogs> {
+        let logs = ContainerLogs::default();
+        let Some(lease) = &self.lease else {
+            return Ok(logs);
+        };
+        let docker = docker::client()?;
+        let options = LogsOptions {
+            stdout: true,
+            stderr: true,
+            tail: MAX_CONTAINER_LOG_FRAMES.to_string(),
+            ..Default::default()
+        };
+        let mut output = docker.logs(&lease.container_id, Some(options));
+        while let Some(frame) = output.next().await {
+            match frame.map_err(|e| docker_error("fetching container logs", e))? {
+                LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                    logs.push(&LogFrame::StdOut(message))
+                }
+                LogOutput::StdErr { message } => logs.push(&LogFrame::StdErr(message)),
+                LogOutput::StdIn { .. } => {}
+            }
+        }
+        Ok(logs)
+    }
+
+    /// The URL under which Localstack is reachable from the host.
+    pub fn endpoint_url(&self) -> &str {
+        &self.endpoint_url
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// Builds the ARN of `resource` in `service`, e.g. `arn("sqs", "my-queue")`.
+    ///
+    /// For global services like IAM, use [`LocalStackInstance::global_arn`].
+    pub fn arn(&self, service: &str, resource: &str) -> String {
+        self.arn_in_region(&self.region, service, resource)
+    }
+
+    /// Like [`LocalStackInstance::arn`], but for a resource in `region`.
+    pub fn arn_in_region(&self, region: &str, service: &str, resource: &str) -> String {
+        format!("arn:aws:{service}:{region}:{}:{resource}", self.account_id)
+    }
+
+    /// Builds the ARN of `resource` in a global `service`, e.g. `global_arn("iam", "role/dummy")`.
+    pub fn global_arn(&self, service: &str, resource: &str) -> String {
+        format!("arn:aws:{service}::{}:{resource}", self.account_id)
+    }
+
+    /// Builds the URL of the SQS-queue named `queue_name`.
+    pub fn queue_url(&self, queue_name: &str) -> String {
+        format!("{}/{}/{queue_name}", self.endpoint_url, self.account_id)
+    }
+
+    pub fn aws_config(&self) -> &SdkConfig {
+        &self.config
+    }
+
+    /// Returns an AWS-Config pointing at this Localstack, but for `region`.
+    pub fn aws_config_for_region(&self, region: &str) -> SdkConfig {
+        self.config
+            .to_builder()
+            .region(aws_config::Region::new(region.to_string()))
+            .build()
+    }
+
+    /// Runs `init` unless `environment` was already provisioned in this Localstack, e.g. by another
+    /// test-process attached to the same container or external endpoint.
+    ///
+    /// Processes provisioning the same Localstack wait for each other, so each environment is only
+    /// provisioned once. An external Localstack is provisioned again once it was restarted.
+    pub async fn provision<F, Fut>(&self, environment: &str, init: F) -> Result<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut lock = StateLock::acquire(&self.lock_name).await?;
+        let mut state = lock.read()?;
+        if self.lease.is_none() {
+            let session_id = get_session_id(&self.endpoint_url).await?;
+            if state.session_id != session_id {
+                state = SharedState {
+                    session_id,
+                    ..SharedState::default()
+                };
+            }
+        }
+        if state.provisioned.contains(environment) {
+            return Ok(());
+        }
+        init().await?;
+        state.provisioned.insert(environment.to_string());
+        lock.write(&state)
+    }
+
+    /// Like [`LocalStackInstance::provision`], but restores `snapshot` instead of running `init` if
+    /// it's current. Otherwise, `init` runs and its result is snapshotted for the next run.
+    ///
+    /// Snapshots require Localstack Pro. If restoring or taking the snapshot fails, e.g. with the
+    /// community-image, this logs a warning and behaves like [`LocalStackInstance::provision`].
+    pub async fn provision_from_snapshot<F, Fut>(
+        &self,
+        environment: &str,
+        snapshot: &Snapshot,
+        init: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        self.provision(environment, || async {
+            if snapshot.is_current() {
+                let restored_at = Instant::now();
+                match snapshot.restore(&self.endpoint_url).await {
+                    Ok(()) => {
+                        tracing::info!(
+                            environment,
+                            snapshot = %snapshot.path().display(),
+                            elapsed = ?restored_at.elapsed(),
+                            "Restored snapshot"
+                        );
+                        return Ok(());
+                    }
+                    Err(e) => tracing::warn!(
+                        environment,
+                        snapshot = %snapshot.path().display(),
+                        "Failed restoring snapshot, provisioning instead: {e}"
+                    ),
+                }
+            }
+            init().await?;
+            if let Err(e) = snapshot.take(&self.endpoint_url).await {
+                tracing::warn!(
+                    environment,
+                    snapshot = %snapshot.path().display(),
+                    "Failed taking snapshot: {e}"
+                );
+            }
+            Ok(())
         })
         .await
+    }
+
+    /// Provisions `environment` via `init` like [`LocalStackInstance::provision`], as the
+    /// environments of this crate do.
+    ///
+    /// If [`SNAPSHOT_DIR_ENV_VAR`] is set, restores a snapshot taken by a previous run instead, see
+    /// [`LocalStackInstance::provision_from_snapshot`]. `fingerprint` adds everything `init`
+    /// provisions to the fingerprint of that snapshot. It may fetch what it fingerprints, e.g. an
+    /// artifact, and is only called if there is a snapshot.
+    pub async fn provision_environment<G, GFut, F, Fut>(
+        &self,
+        environment: &str,
+        fingerprint: G,
+        init: F,
+    ) -> Result<()>
+    where
+        G: FnOnce(Snapshot) -> GFut,
+        GFut: Future<Output = Result<Snapshot>>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        match Snapshot::from_env(environment) {
+            Some(snapshot) => {
+                let snapshot = fingerprint(snapshot).await?;
+                self.provision_from_snapshot(environment, &snapshot, init)
+                    .await
+            }
+            None => self.provision(environment, init).await,
+        }
+    }
+
+    /// Waits until no test of another process uses this Localstack and reserves it for the caller
+    /// until the returned guard is dropped.
+    ///
+    /// Used by the test-macros, because `serial_test` only serializes tests within one process.
+    pub async fn exclusive_use(&self) -> Result<ExclusiveUse> {
+        ExclusiveUse::acquire(&self.lock_name).await
+    }
+
+    /// Returns a client of type `C` pointing at this Localstack.
+    ///
+    /// Clients are created on first use and shared afterward, so this is cheap to call repeatedly:
+    ///
+    /// ```no_run
+    /// # async fn example(localstack: &test_api::localstack::LocalStackInstance) {
+    /// let s3_client = localstack.client::<aws_sdk_s3::Client>();
+    /// let sns_client = localstack.client::<aws_sdk_sns::Client>();
+    /// # }
+    /// ```
+    pub fn client<C: AwsClient>(&self) -> C {
+        self.client_in_region(&self.region)
+    }
+
+    /// Like [`LocalStackInstance::client`], but for `region`.
+    pub fn client_in_region<C: AwsClient>(&self, region: &str) -> C {
+        let mut clients = self
+            .clients
+            .lock()
+            .expect("shouldn't fail locking clients because no client-construction panics");
+        clients
+            .entry((TypeId::of::<C>(), region.to_string()))
+            .or_insert_with(|| Box::new(C::from_sdk_config(&self.aws_config_for_region(region))))
+            .downcast_ref::<C>()
+            .expect("shouldn't fail downcasting client because it's keyed by its TypeId")
+            .clone()
+    }
 }
 
-static DYNAMODB_CLIENT: OnceCell<Client> = OnceCell::const_new();
-/// Lazily initializes and returns a shared DynamoDB client.
-pub async fn get_dynamodb_client() -> &'static Client {
-    DYNAMODB_CLIENT
-        .get_or_init(|| async { Client::new(get_aws_config().await) })
-        .await
+impl Drop for LocalStackInstance {
+    fn drop(&mut self) {
+        let Some(lease) = self.lease.take() else {
+            return;
+        };
+        unregister_from_teardown_at_exit(&lease);
+        let container = self.container.take();
+        if lease.release().unwrap_or(false) && !lease.keep_alive {
+            tear_down_blocking(&lease);
+        }
+        // Skips the removal testcontainers does on drop, because the container was either removed
+        // above, is kept alive or is still used by other processes.
+        std::mem::forget(container);
+    }
 }
 
-static SQS_CLIENT: OnceCell<aws_sdk_sqs::Client> = OnceCell::const_new();
-/// Lazily initializes and returns a shared SQS client.
-pub async fn get_sqs_client() -> &'static aws_sdk_sqs::Client {
-    SQS_CLIENT
-        .get_or_init(|| async {
-            let config = get_aws_config().await;
-            aws_sdk_sqs::Client::new(&config)
+/// An AWS-SDK client that can be obtained through [`LocalStackInstance::client`].
+///
+/// Implemented for the clients of all services this crate depends on. Implement it for any
+/// other SDK-client to use it with Localstack, too.
+pub trait AwsClient: Clone + Send + Sync + 'static {
+    fn from_sdk_config(config: &SdkConfig) -> Self;
+}
+
+macro_rules! impl_aws_client {
+    ($($client:ty),* $(,)?) => {
+        $(
+            impl AwsClient for $client {
+                fn from_sdk_config(config: &SdkConfig) -> Self {
+                    <$client>::new(config)
+                }
+            }
+        )*
+    };
+}
+
+impl_aws_client!(
+    aws_sdk_dynamodb::Client,
+    aws_sdk_sqs::Client,
+    aws_sdk_lambda::Client,
+    aws_sdk_sns::Client,
+    aws_sdk_eventbridge::Client,
+    aws_sdk_ssm::Client,
+    aws_sdk_secretsmanager::Client,
+    aws_sdk_cloudwatchlogs::Client,
+);
+
+impl AwsClient for aws_sdk_s3::Client {
+    fn from_sdk_config(config: &SdkConfig) -> Self {
+        // Localstack can't resolve virtual-hosted-style bucket addresses like
+        // `<bucket>.localhost`.
+        let s3_config = aws_sdk_s3::config::Builder::from(config)
+            .force_path_style(true)
+            .build();
+        aws_sdk_s3::Client::from_conf(s3_config)
+    }
+}
+
+/// Number of log-frames [`ContainerLogs`] keeps. Older ones are dropped.
+pub const MAX_CONTAINER_LOG_FRAMES: usize = 10_000;
+
+/// Collects stdout and stderr of a Localstack container in the order they were written.
+///
+/// Keeps the last [`MAX_CONTAINER_LOG_FRAMES`] frames, so a long-running container doesn't grow
+/// them without bounds. Clones share the same buffer, so a clone handed to the container keeps
+/// filling the original.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerLogs {
+    frames: Arc<Mutex<LogFrames>>,
+}
+
+#[derive(Debug, Default)]
+struct LogFrames {
+    frames: VecDeque<LogFrame>,
+    dropped: usize,
+}
+
+impl ContainerLogs {
+    fn push(&self, frame: &LogFrame) {
+        let mut frames = self.frames();
+        if frames.frames.len() == MAX_CONTAINER_LOG_FRAMES {
+            frames.frames.pop_front();
+            frames.dropped += 1;
+        }
+        frames.frames.push_back(frame.clone());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames().frames.is_empty()
+    }
+
+    fn frames(&self) -> std::sync::MutexGuard<'_, LogFrames> {
+        self.frames
+            .lock()
+            .expect("shouldn't fail locking container logs because no writer panics")
+    }
+
+    /// Writes the logs to `target/test-api/logs/<test_name>.log`.
+    ///
+    /// Falls back to stderr (and thus the test output) if the file can't be written.
+    pub fn dump(&self, test_name: &str) {
+        let content = format!("test: {test_name}\n\n{self}");
+        let path = logs_dir().join(format!("{}.log", test_name.replace("::", "__")));
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, &content));
+        match written {
+            Ok(()) => eprintln!(
+                "Wrote Localstack logs of '{test_name}' to '{}'",
+                path.display()
+            ),
+            Err(e) => eprintln!(
+                "Failed writing Localstack logs to '{}' ({e}), dumping them here:\n{content}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ContainerLogs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frames = self.frames();
+        if frames.dropped > 0 {
+            writeln!(f, "[{} earlier log-frames dropped]", frames.dropped)?;
+        }
+        for frame in &frames.frames {
+            let (stream, bytes) = match frame {
+                LogFrame::StdOut(bytes) => ("stdout", bytes),
+                LogFrame::StdErr(bytes) => ("stderr", bytes),
+            };
+            for line in String::from_utf8_lossy(bytes).lines() {
+                writeln!(f, "[{stream}] {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn logs_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("CARGO_MANIFEST_DIR").map(|dir| PathBuf::from(dir).join("target"))
         })
-        .await
+        .unwrap_or_else(|| PathBuf::from("target"))
+        .join("test-api")
+        .join("logs")
+}
+
+/// Dumps the [`logs`](LocalStackInstance::logs) of a Localstack when dropped during a panic, e.g.
+/// a failing assertion in a test.
+///
+/// Used by the test-macros to preserve the logs of failed tests.
+#[must_use]
+pub struct DumpLogsOnPanic<'a> {
+    test_name: &'a str,
+    localstack: &'a LocalStackInstance,
+}
+
+impl<'a> DumpLogsOnPanic<'a> {
+    pub fn new(test_name: &'a str, localstack: &'a LocalStackInstance) -> Self {
+        Self {
+            test_name,
+            localstack,
+        }
+    }
 }
 
-static LAMBDA_CLIENT: OnceCell<aws_sdk_lambda::Client> = OnceCell::const_new();
-/// Lazily initializes and returns a shared Lambda client.
-pub async fn get_lambda_client() -> &'static aws_sdk_lambda::Client {
-    LAMBDA_CLIENT
-        .get_or_init(|| async {
-            let config = get_aws_config().await;
-            aws_sdk_lambda::Client::new(&config)
+impl Drop for DumpLogsOnPanic<'_> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        match block_on_own_runtime("fetching container logs", self.localstack.logs()) {
+            Ok(logs) if !logs.is_empty() => logs.dump(self.test_name),
+            Ok(_) => {}
+            Err(e) => eprintln!(
+                "Failed fetching Localstack logs of '{}': {e}",
+                self.test_name
+            ),
+        }
+    }
+}
+
+/// Adds `part` to `hasher`, prefixed with its length so moving bytes between parts changes the hash.
+fn hash_part(hasher: &mut Sha256, part: impl AsRef<[u8]>) {
+    let part = part.as_ref();
+    hasher.update((part.len() as u64).to_le_bytes());
+    hasher.update(part);
+}
+
+/// Whether cargo-nextest runs this test, which runs every test in its own process.
+fn is_process_per_test() -> bool {
+    std::env::var_os("NEXTEST").is_some()
+}
+
+pub(crate) fn is_env_flag_set(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Returns the name of the running container publishing `host_port`, if any.
+async fn find_container_bound_to(docker: &Docker, host_port: u16) -> Option<String> {
+    docker
+        .list_containers(None::<ListContainersOptions<String>>)
+        .await
+        .ok()?
+        .into_iter()
+        .find(|container| {
+            container
+                .ports
+                .iter()
+                .flatten()
+                .any(|port| port.public_port == Some(host_port))
+        })
+        .map(|container| {
+            container
+                .names
+                .and_then(|names| names.into_iter().next())
+                .map_or_else(
+                    || container.id.unwrap_or_default(),
+                    |name| name.trim_start_matches('/').to_string(),
+                )
         })
+}
+
+/// Leases to release when the test-binary exits.
+static LEASES: Mutex<Vec<Lease>> = Mutex::new(Vec::new());
+static REGISTER_TEARDOWN_AT_EXIT: Once = Once::new();
+
+/// Runs [`tear_down`] via [`block_on_own_runtime`], reporting failures via tracing.
+fn tear_down_blocking(lease: &Lease) {
+    if let Err(e) = block_on_own_runtime("tearing down the container", tear_down(lease)) {
+        tracing::warn!(
+            container = lease.container_name,
+            "Failed tearing down container: {e}"
+        );
+    }
+}
+
+/// Runs `future` on a dedicated runtime and thread.
+///
+/// Required in `Drop`, possibly on a thread of another runtime, and at exit, when no runtime is
+/// left.
+fn block_on_own_runtime<T: Send>(
+    operation: &str,
+    future: impl Future<Output = Result<T>> + Send,
+) -> Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| Error::Docker {
+                        operation: format!("starting a runtime for {operation}"),
+                        source: e.into(),
+                    })
+                    .and_then(|runtime| runtime.block_on(future))
+            })
+            .join()
+            .unwrap_or_else(|_| {
+                Err(Error::Docker {
+                    operation: operation.to_string(),
+                    source: "it panicked".into(),
+                })
+            })
+    })
+}
+
+fn register_for_teardown_at_exit(lease: Lease) {
+    // Elsewhere, the leases of instances that are never dropped are left to the next start.
+    #[cfg(any(unix, windows))]
+    REGISTER_TEARDOWN_AT_EXIT.call_once(|| {
+        // SAFETY: `tear_down_at_exit` is a plain function that doesn't unwind.
+        unsafe {
+            libc::atexit(tear_down_at_exit);
+        }
+    });
+    LEASES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(lease);
+}
+
+fn unregister_from_teardown_at_exit(lease: &Lease) {
+    let mut leases = LEASES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(index) = leases.iter().position(|registered| registered == lease) {
+        leases.swap_remove(index);
+    }
+}
+
+/// Releases the leases left at exit, e.g. of instances in statics, which are never dropped.
+///
+/// Skips any lease whose lock is taken instead of waiting, because that could hang the exit. Its
+/// container is then left to the next [`LocalStackBuilder::start`] using the same name, which
+/// replaces or reuses it.
+#[cfg(any(unix, windows))]
+extern "C" fn tear_down_at_exit() {
+    let leases = match LEASES.try_lock() {
+        Ok(mut leases) => std::mem::take(&mut *leases),
+        Err(TryLockError::Poisoned(poisoned)) => std::mem::take(&mut *poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    for lease in leases {
+        if matches!(lease.try_release(), Ok(Some(true))) && !lease.keep_alive {
+            tear_down_blocking(&lease);
+        }
+    }
+}
+
+/// Polls Localstack's health-endpoint until it responds and every service in `services` reports
+/// to be `available` or `running`.
+///
+/// Fails with [`Error::NotReady`] if this doesn't happen within `timeout`.
+async fn wait_until_ready(
+    endpoint_url: &str,
+    services: &[String],
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let (pending, last_status) = match get_health(endpoint_url).await {
+            Ok(health) => {
+                let pending = services
+                    .iter()
+                    .filter(|service| !is_service_ready(&health, service))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if pending.is_empty() {
+                    return Ok(());
+                }
+                (pending, health.to_string())
+            }
+            Err(e) => (services.to_vec(), e.to_string()),
+        };
+
+        if Instant::now() >= deadline {
+            return Err(Error::NotReady {
+                endpoint_url: endpoint_url.to_string(),
+                services: pending,
+                last_status,
+                timeout,
+                logs: ContainerLogs::default(),
+            });
+        }
+        tokio::time::sleep(READINESS_POLL_INTERVAL).await;
+    }
+}
+
+async fn get_health(endpoint_url: &str) -> reqwest::Result<serde_json::Value> {
+    get_json(&format!("{endpoint_url}/_localstack/health")).await
+}
+
+/// The id of the current session of the Localstack at `endpoint_url`, which changes on restart.
+async fn get_session_id(endpoint_url: &str) -> Result<String> {
+    let info = get_json(&format!("{endpoint_url}/_localstack/info"))
         .await
+        .map_err(|e| Error::Localstack {
+            operation: format!("reading the session of '{endpoint_url}'"),
+            source: e.into(),
+        })?;
+    Ok(info["session_id"].as_str().unwrap_or_default().to_string())
+}
+
+/// Name an external Localstack is coordinated on, derived from its endpoint so every environment
+/// using it shares the same locks.
+fn external_lock_name(endpoint_url: &str) -> String {
+    let endpoint = endpoint_url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    format!("external-{endpoint}")
+}
+
+async fn get_json(url: &str) -> reqwest::Result<serde_json::Value> {
+    reqwest::get(url).await?.error_for_status()?.json().await
+}
+
+/// Polls Localstack's init-endpoint until all scripts in [`INIT_READY_DIR`] completed.
+///
+/// Fails with [`Error::InitScripts`] as soon as a script fails, or if they don't complete within
+/// `timeout`.
+async fn wait_until_init_scripts_completed(endpoint_url: &str, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let (failed, pending) =
+            match get_json(&format!("{endpoint_url}/_localstack/init/ready")).await {
+                Ok(status) => {
+                    let scripts = status["scripts"].as_array().cloned().unwrap_or_default();
+                    let names_in_state = |matches_state: fn(&str) -> bool| {
+                        scripts
+                            .iter()
+                            .filter(|script| {
+                                matches_state(script["state"].as_str().unwrap_or_default())
+                            })
+                            .filter_map(|script| script["name"].as_str().map(str::to_string))
+                            .collect::<Vec<_>>()
+                    };
+                    let failed = names_in_state(|state| state == "ERROR");
+                    if failed.is_empty() && status["completed"].as_bool() == Some(true) {
+                        return Ok(());
+                    }
+                    (
+                        failed,
+                        names_in_state(|state| state != "SUCCESSFUL" && state != "ERROR"),
+                    )
+                }
+                Err(e) => (Vec::new(), vec![format!("<init-status unavailable: {e}>")]),
+            };
+
+        if !failed.is_empty() || Instant::now() >= deadline {
+            return Err(Error::InitScripts {
+                endpoint_url: endpoint_url.to_string(),
+                failed,
+                pending,
+                timeout,
+                logs: ContainerLogs::default(),
+            });
+        }
+        tokio::time::sleep(READINESS_POLL_INTERVAL).await;
+    }
+}
+
+fn is_service_ready(health: &serde_json::Value, service: &str) -> bool {
+    matches!(
+        health["services"][service].as_str(),
+        Some("available" | "running")
+    )
+}
+
+/// Returns the URL under which the given Localstack container is reachable from the host.
+///
+/// The container binds a random free host-port, so this has to be resolved at runtime.
+async fn get_endpoint_url(
+    container: &ContainerAsync<LocalStack>,
+) -> Result<String, TestcontainersError> {
+    let host = container.get_host().await?;
+    let port = container.get_host_port_ipv4(LOCALSTACK_PORT).await?;
+    Ok(format!("http://{host}:{port}"))
+}
+
+/// Returns a DynamoDB client for the given Localstack.
+pub fn get_dynamodb_client(localstack: &LocalStackInstance) -> Client {
+    localstack.client()
+}
+
+/// Returns a DynamoDB client for the given Localstack in `region`.
+pub fn get_dynamodb_client_in_region(localstack: &LocalStackInstance, region: &str) -> Client {
+    localstack.client_in_region(region)
+}
+
+/// Returns an SQS client for the given Localstack.
+pub fn get_sqs_client(localstack: &LocalStackInstance) -> aws_sdk_sqs::Client {
+    localstack.client()
+}
+
+/// Returns an SQS client for the given Localstack in `region`.
+pub fn get_sqs_client_in_region(
+    localstack: &LocalStackInstance,
+    region: &str,
+) -> aws_sdk_sqs::Client {
+    localstack.client_in_region(region)
+}
+
+/// Returns a Lambda client for the given Localstack.
+pub fn get_lambda_client(localstack: &LocalStackInstance) -> aws_sdk_lambda::Client {
+    localstack.client()
+}
+
+/// Returns a Lambda client for the given Localstack in `region`.
+pub fn get_lambda_client_in_region(
+    localstack: &LocalStackInstance,
+    region: &str,
+) -> aws_sdk_lambda::Client {
+    localstack.client_in_region(region)
 }
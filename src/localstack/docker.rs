@@ -0,0 +1,254 @@
+//! Connects to the Docker daemon testcontainers starts the containers on and removes containers
+//! from it.
+//!
+//! Bollard's defaults only look at `DOCKER_HOST` and `/var/run/docker.sock`, whereas testcontainers
+//! also reads `~/.testcontainers.properties` and the sockets of rootless Docker. Resolving the
+//! host the same way keeps pinging, log-fetching and tearing down on the daemon the containers
+//! actually run on.
+
+use super::shared::Lease;
+use crate::error::{Error, Result};
+use bollard::container::{ListContainersOptions, RemoveContainerOptions, StopContainerOptions};
+use bollard::{API_DEFAULT_VERSION, Docker};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The properties-file testcontainers reads from the home-directory.
+const PROPERTIES_FILE: &str = ".testcontainers.properties";
+
+const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+#[cfg(windows)]
+const DEFAULT_DOCKER_HOST: &str = "npipe:////./pipe/docker_engine";
+#[cfg(not(windows))]
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+
+/// Same as testcontainers uses.
+const TIMEOUT_SECS: u64 = 120;
+const GRACEFUL_STOP_TIMEOUT_SECS: i64 = 10;
+
+/// Connects to the Docker daemon.
+///
+/// Connecting doesn't contact the daemon yet, so callers ping it to detect that it's unavailable.
+pub(super) fn client() -> Result<Docker> {
+    let config = DockerConfig::load();
+    let host = config.host();
+    let connected = match host.split_once("://").map(|(scheme, _)| scheme) {
+        Some("https") => return config.connect_with_ssl(&host),
+        Some("http" | "tcp") if config.tls_verify => return config.connect_with_ssl(&host),
+        Some("http" | "tcp") => Docker::connect_with_http(&host, TIMEOUT_SECS, API_DEFAULT_VERSION),
+        #[cfg(unix)]
+        Some("unix") => Docker::connect_with_unix(&host, TIMEOUT_SECS, API_DEFAULT_VERSION),
+        #[cfg(windows)]
+        Some("npipe") => Docker::connect_with_named_pipe(&host, TIMEOUT_SECS, API_DEFAULT_VERSION),
+        _ => Err(bollard::errors::Error::UnsupportedURISchemeError { uri: host.clone() }),
+    };
+    connected.map_err(|e| Error::DockerUnavailable { source: e.into() })
+}
+
+/// The host under which the daemon publishes container-ports, like testcontainers'
+/// `ContainerAsync::get_host`: the host of a remote daemon, otherwise `localhost`, or the gateway of
+/// the bridge-network when running inside a container.
+pub(super) async fn hostname(docker: &Docker) -> String {
+    let host = DockerConfig::load().host();
+    if let Ok(url) = reqwest::Url::parse(&host)
+        && matches!(url.scheme(), "tcp" | "http" | "https")
+        && let Some(hostname) = url.host_str()
+    {
+        return hostname.to_string();
+    }
+    if !Path::new("/.dockerenv").exists() {
+        return "localhost".to_string();
+    }
+    docker
+        .inspect_network::<String>("bridge", None)
+        .await
+        .ok()
+        .and_then(|network| network.ipam)
+        .and_then(|ipam| ipam.config)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|config| config.gateway)
+        .find(|gateway| !gateway.trim().is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// The subset of testcontainers' configuration that determines the Docker daemon.
+///
+/// Environment-variables take precedence over the properties-file, except for `tc.host`, which
+/// takes precedence over everything.
+#[derive(Debug)]
+struct DockerConfig {
+    tc_host: Option<String>,
+    host: Option<String>,
+    tls_verify: bool,
+    cert_path: Option<PathBuf>,
+}
+
+impl DockerConfig {
+    fn load() -> Self {
+        let mut properties = home_dir()
+            .and_then(|dir| std::fs::read_to_string(dir.join(PROPERTIES_FILE)).ok())
+            .map(|content| parse_properties(&content))
+            .unwrap_or_default();
+        let env = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Self {
+            tc_host: properties.remove("tc.host"),
+            host: env("DOCKER_HOST").or_else(|| properties.remove("docker.host")),
+            tls_verify: env("DOCKER_TLS_VERIFY")
+                .or_else(|| properties.remove("docker.tls.verify"))
+                .is_some_and(|value| value == "1"),
+            cert_path: env("DOCKER_CERT_PATH")
+                .or_else(|| properties.remove("docker.cert.path"))
+                .map(PathBuf::from),
+        }
+    }
+
+    /// The Docker host: `tc.host`, `DOCKER_HOST`, `docker.host`, the default socket, the sockets of
+    /// rootless Docker and finally the default socket anyway, just like testcontainers.
+    fn host(&self) -> String {
+        if let Some(host) = self.tc_host.as_ref().or(self.host.as_ref()) {
+            return host.clone();
+        }
+        if cfg!(unix) {
+            let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+            let candidates = [
+                Some(PathBuf::from(DEFAULT_DOCKER_SOCKET)),
+                runtime_dir.map(|dir| dir.join(".docker/run/docker.sock")),
+                home_dir().map(|dir| dir.join(".docker/run/docker.sock")),
+                home_dir().map(|dir| dir.join(".docker/desktop/docker.sock")),
+            ];
+            if let Some(socket) = candidates.into_iter().flatten().find(|path| path.exists()) {
+                return format!("unix://{}", socket.display());
+            }
+        }
+        DEFAULT_DOCKER_HOST.to_string()
+    }
+
+    fn connect_with_ssl(&self, host: &str) -> Result<Docker> {
+        let Some(cert_path) = &self.cert_path else {
+            return Err(Error::DockerUnavailable {
+                source: format!(
+                    "'{host}' requires TLS, but neither DOCKER_CERT_PATH nor docker.cert.path is set"
+                )
+                .into(),
+            });
+        };
+        Docker::connect_with_ssl(
+            host,
+            &cert_path.join("key.pem"),
+            &cert_path.join("cert.pem"),
+            &cert_path.join("ca.pem"),
+            TIMEOUT_SECS,
+            API_DEFAULT_VERSION,
+        )
+        .map_err(|e| Error::DockerUnavailable { source: e.into() })
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Parses the `key=value`- and `key: value`-lines of a Java properties-file, skipping comments.
+///
+/// Line-continuations and escapes aren't supported, as the Docker-related properties need neither.
+fn parse_properties(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['#', '!']))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(['=', ':'])?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+pub(super) fn docker_error(operation: &str, source: bollard::errors::Error) -> Error {
+    Error::Docker {
+        operation: operation.to_string(),
+        source: source.into(),
+    }
+}
+
+pub(super) fn is_not_found(e: &bollard::errors::Error) -> bool {
+    matches!(
+        e,
+        bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            ..
+        }
+    )
+}
+
+/// Removes the container of `name` and the Lambda-containers it spawned, if they exist.
+pub(super) async fn cleanup_existing_container(docker: &Docker, name: &str) -> Result<()> {
+    remove_container(docker, name).await?;
+    remove_lambda_containers(docker, name).await
+}
+
+/// Removes the container and its volumes, if it exists.
+async fn remove_container(docker: &Docker, name_or_id: &str) -> Result<()> {
+    let options = RemoveContainerOptions {
+        force: true,
+        v: true,
+        ..Default::default()
+    };
+    match docker.remove_container(name_or_id, Some(options)).await {
+        Err(e) if !is_not_found(&e) => Err(docker_error(
+            &format!("removing container '{name_or_id}'"),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the container and the Lambda-containers it spawned.
+pub(super) async fn tear_down(lease: &Lease) -> Result<()> {
+    let docker = client()?;
+    // Stopping gracefully lets Localstack shut down its Lambda-containers itself.
+    let stopped = docker
+        .stop_container(
+            &lease.container_id,
+            Some(StopContainerOptions {
+                t: GRACEFUL_STOP_TIMEOUT_SECS,
+            }),
+        )
+        .await;
+    match stopped {
+        // 304 means it's already stopped.
+        Err(bollard::errors::Error::DockerResponseServerError {
+            status_code: 304, ..
+        }) => {}
+        Err(e) if !is_not_found(&e) => {
+            return Err(docker_error(
+                &format!("stopping container '{}'", lease.container_id),
+                e,
+            ));
+        }
+        _ => {}
+    }
+    remove_lambda_containers(&docker, &lease.container_name).await?;
+    remove_container(&docker, &lease.container_id).await
+}
+
+async fn remove_lambda_containers(docker: &Docker, main_container_name: &str) -> Result<()> {
+    let filters = HashMap::from([(
+        "name".to_string(),
+        vec![format!("^{main_container_name}-lambda-")],
+    )]);
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters,
+            ..Default::default()
+        }))
+        .await
+        .map_err(|e| docker_error("listing Lambda-containers", e))?;
+    for id in containers.into_iter().filter_map(|container| container.id) {
+        remove_container(docker, &id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! Coordinates processes sharing the container of one name, e.g. concurrently running
+//! test-binaries.
+//!
+//! Every container-name has a state-file in the temp-dir recording the container and the
+//! processes holding it. The file is only accessed while holding an exclusive lock on it, which
+//! also serializes starting the container.
+//!
+//! Running tests is coordinated through a separate lock-file, see [`ExclusiveUse`].
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The container behind a container-name, the processes currently using it and the environments
+/// provisioned in it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct SharedState {
+    pub(super) config_hash: String,
+    pub(super) container_id: String,
+    pub(super) endpoint_url: String,
+    pub(super) holders: Vec<u32>,
+    #[serde(default)]
+    pub(super) provisioned: BTreeSet<String>,
+    /// Session of an external Localstack the provisioned environments were provisioned in.
+    #[serde(default)]
+    pub(super) session_id: String,
+}
+
+/// Exclusive access to the [`SharedState`] of a container-name, released on drop.
+#[derive(Debug)]
+pub(super) struct StateLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl StateLock {
+    /// Waits for the lock without blocking the async-runtime.
+    pub(super) async fn acquire(container_name: &str) -> Result<Self> {
+        let path = lock_path(container_name, "state");
+        let file = lock_file_async(path.clone()).await?;
+        Ok(Self { file, path })
+    }
+
+    pub(super) fn acquire_blocking(container_name: &str) -> Result<Self> {
+        let path = lock_path(container_name, "state");
+        let file = lock_file(&path)?;
+        Ok(Self { file, path })
+    }
+
+    /// Like [`StateLock::acquire_blocking`], but `None` if another process holds the lock.
+    pub(super) fn try_acquire(container_name: &str) -> Result<Option<Self>> {
+        let path = lock_path(container_name, "state");
+        let file = open_lock_file(&path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { file, path })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(Error::SharedState {
+                path,
+                source: e.into(),
+            }),
+        }
+    }
+
+    /// Reads the state, dropping holders that exited without releasing it, e.g. after a crash.
+    ///
+    /// An empty or unreadable file yields the default state, i.e. nobody holds the container.
+    pub(super) fn read(&mut self) -> Result<SharedState> {
+        let mut content = String::new();
+        self.file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.read_to_string(&mut content))
+            .map_err(|e| self.error(e.into()))?;
+        let mut state = serde_json::from_str::<SharedState>(&content).unwrap_or_default();
+        state.holders.retain(|pid| is_process_alive(*pid));
+        Ok(state)
+    }
+
+    pub(super) fn write(&mut self, state: &SharedState) -> Result<()> {
+        let content = serde_json::to_vec(state).map_err(|e| self.error(e.into()))?;
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| self.file.write_all(&content))
+            .map_err(|e| self.error(e.into()))
+    }
+
+    fn error(&self, source: crate::error::BoxError) -> Error {
+        Error::SharedState {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+/// This process' claim on a shared container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Lease {
+    pub(super) container_name: String,
+    pub(super) container_id: String,
+    pub(super) keep_alive: bool,
+}
+
+impl Lease {
+    /// Records the current process as holder of the container in `state`.
+    pub(super) fn acquire(
+        lock: &mut StateLock,
+        mut state: SharedState,
+        container_name: &str,
+        keep_alive: bool,
+    ) -> Result<Self> {
+        state.holders.push(std::process::id());
+        lock.write(&state)?;
+        Ok(Self {
+            container_name: container_name.to_string(),
+            container_id: state.container_id,
+            keep_alive,
+        })
+    }
+
+    /// Removes the current process from the holders. Returns whether it was the last one.
+    ///
+    /// Blocks, because it runs on drop, when there may be no async-runtime.
+    pub(super) fn release(&self) -> Result<bool> {
+        let lock = StateLock::acquire_blocking(&self.container_name)?;
+        self.release_locked(lock)
+    }
+
+    /// Like [`Lease::release`], but `None` without releasing if another process holds the
+    /// state-lock.
+    pub(super) fn try_release(&self) -> Result<Option<bool>> {
+        StateLock::try_acquire(&self.container_name)?
+            .map(|lock| self.release_locked(lock))
+            .transpose()
+    }
+
+    fn release_locked(&self, mut lock: StateLock) -> Result<bool> {
+        let mut state = lock.read()?;
+        if state.container_id != self.container_id {
+            // Another process replaced the container after this one was gone.
+            return Ok(false);
+        }
+        // A process holds the container once per instance.
+        let pid = std::process::id();
+        if let Some(index) = state.holders.iter().position(|holder| *holder == pid) {
+            state.holders.swap_remove(index);
+        }
+        // A container kept alive stays recorded, so the next process can attach to it.
+        if state.holders.is_empty() && !self.keep_alive {
+            state = SharedState::default();
+        }
+        lock.write(&state)?;
+        Ok(state.holders.is_empty())
+    }
+}
+
+/// Exclusive use of a Localstack by a single test across all processes, released on drop.
+///
+/// Obtained through [`LocalStackInstance::exclusive_use`](super::LocalStackInstance::exclusive_use).
+#[derive(Debug)]
+#[must_use]
+pub struct ExclusiveUse {
+    _file: File,
+}
+
+impl ExclusiveUse {
+    pub(super) async fn acquire(container_name: &str) -> Result<Self> {
+        let file = lock_file_async(lock_path(container_name, "exclusive")).await?;
+        Ok(Self { _file: file })
+    }
+}
+
+fn lock_path(container_name: &str, purpose: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("test-api-{container_name}.{purpose}.lock"))
+}
+
+/// Opens `path` and waits until this process holds an exclusive lock on it.
+fn lock_file(path: &Path) -> Result<File> {
+    let file = open_lock_file(path)?;
+    file.lock().map_err(|e| Error::SharedState {
+        path: path.to_path_buf(),
+        source: e.into(),
+    })?;
+    Ok(file)
+}
+
+fn open_lock_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| Error::SharedState {
+            path: path.to_path_buf(),
+            source: e.into(),
+        })
+}
+
+/// Like [`lock_file`], but without blocking the async-runtime while waiting.
+async fn lock_file_async(path: PathBuf) -> Result<File> {
+    let path_of_error = path.clone();
+    tokio::task::spawn_blocking(move || lock_file(&path))
+        .await
+        .map_err(|e| Error::SharedState {
+            path: path_of_error,
+            source: e.into(),
+        })?
+}
+
+#[cfg(unix)]
+fn is_process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: Signal 0 only checks whether the process exists and may be signalled.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a portable way to check, every holder is assumed to be alive. A holder that crashed
+/// then keeps its container from being replaced, so starting it with another configuration waits
+/// for [`LocalStackBuilder::with_queue_timeout`] in vain.
+///
+/// [`LocalStackBuilder::with_queue_timeout`]: super::LocalStackBuilder::with_queue_timeout
+#[cfg(not(unix))]
+fn is_process_alive(_pid: u32) -> bool {
+    true
+}
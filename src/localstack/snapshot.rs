@@ -0,0 +1,120 @@
+//! Snapshots of a provisioned Localstack, restored instead of provisioning it again.
+//!
+//! Snapshots are exported from and imported into Localstack through its state-endpoints
+//! (`/_localstack/pods`), which require Localstack Pro. Without them, provisioning simply runs as
+//! usual.
+
+use crate::error::BoxError;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// If set, environments provisioned through [`LocalStackInstance::provision_from_snapshot`]
+/// are snapshotted to and restored from this directory.
+///
+/// [`LocalStackInstance::provision_from_snapshot`]: super::LocalStackInstance::provision_from_snapshot
+pub const SNAPSHOT_DIR_ENV_VAR: &str = "TEST_API_SNAPSHOT_DIR";
+
+/// A snapshot of the state of a Localstack in a file, together with the fingerprint of what
+/// produced it.
+///
+/// The fingerprint covers everything the provisioned state depends on, e.g. a version of the
+/// provisioning code and its fixtures. A snapshot whose fingerprint differs is outdated and taken
+/// again. Fingerprints are SHA-256 digests, so they stay the same across Rust releases:
+///
+/// ```no_run
+/// use test_api::localstack::Snapshot;
+///
+/// let snapshot = Snapshot::new("target/snapshots/my-environment.zip")
+///     .with_fingerprint(env!("CARGO_PKG_VERSION"))
+///     .with_fingerprint(include_str!("../../data/items.json"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    path: PathBuf,
+    hasher: Sha256,
+}
+
+impl Snapshot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Snapshot of `environment` in [`SNAPSHOT_DIR_ENV_VAR`], or `None` if that isn't set.
+    pub fn from_env(environment: &str) -> Option<Self> {
+        let dir = std::env::var_os(SNAPSHOT_DIR_ENV_VAR)?;
+        Some(Self::new(
+            Path::new(&dir).join(format!("{environment}.zip")),
+        ))
+    }
+
+    /// Adds `part` to the fingerprint, invalidating snapshots taken without it.
+    pub fn with_fingerprint(mut self, part: impl AsRef<[u8]>) -> Self {
+        let part = part.as_ref();
+        // Prefixed with its length, so moving bytes between parts changes the fingerprint.
+        self.hasher.update((part.len() as u64).to_le_bytes());
+        self.hasher.update(part);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn fingerprint(&self) -> String {
+        self.hasher
+            .clone()
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Whether the snapshot exists and was taken with the current fingerprint.
+    pub fn is_current(&self) -> bool {
+        self.path.is_file()
+            && std::fs::read_to_string(self.fingerprint_path())
+                .is_ok_and(|fingerprint| fingerprint == self.fingerprint())
+    }
+
+    /// Imports the snapshot into the Localstack at `endpoint_url`.
+    pub(super) async fn restore(&self, endpoint_url: &str) -> Result<(), BoxError> {
+        let state = tokio::fs::read(&self.path).await?;
+        reqwest::Client::new()
+            .post(format!("{endpoint_url}/_localstack/pods"))
+            .body(state)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Exports the state of the Localstack at `endpoint_url` into the snapshot.
+    ///
+    /// The snapshot is replaced atomically, so concurrent processes never restore a partial one.
+    pub(super) async fn take(&self, endpoint_url: &str) -> Result<(), BoxError> {
+        let state = reqwest::get(format!("{endpoint_url}/_localstack/pods/state"))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let partial_path = self
+            .path
+            .with_extension(format!("{}.partial", std::process::id()));
+        tokio::fs::write(&partial_path, &state).await?;
+        tokio::fs::rename(&partial_path, &self.path).await?;
+        tokio::fs::write(self.fingerprint_path(), self.fingerprint()).await?;
+        Ok(())
+    }
+
+    fn fingerprint_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".fingerprint");
+        PathBuf::from(path)
+    }
+}
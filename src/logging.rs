@@ -0,0 +1,494 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::instrument::{WithDispatch, WithSubscriber};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Dispatch, Event, Level, Subscriber};
+use tracing_subscriber::fmt::TestWriter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Output format of the subscriber installed by [`TracingSetup::init`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Multi-line, human-readable output. Best for local runs.
+    #[default]
+    Pretty,
+    /// Single-line, human-readable output.
+    Compact,
+    /// One JSON-object per line, including the current span. Best for CI.
+    Json,
+}
+
+/// Configures a global `tracing`-subscriber for tests.
+///
+/// Nothing is installed unless [`TracingSetup::init`] is called, so tests are free to install
+/// their own subscriber instead.
+///
+/// The level is taken from `RUST_LOG`, falling back to [`TracingSetup::with_default_level`].
+///
+/// ```no_run
+/// use test_api::logging::{LogFormat, TracingSetup};
+///
+/// let _ = TracingSetup::new()
+///     .with_format(LogFormat::Compact)
+///     .with_test_writer()
+///     .init();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracingSetup {
+    format: LogFormat,
+    default_level: Level,
+    test_writer: bool,
+}
+
+impl Default for TracingSetup {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            default_level: Level::INFO,
+            test_writer: false,
+        }
+    }
+}
+
+impl TracingSetup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the level used if `RUST_LOG` isn't set. Defaults to [`Level::INFO`].
+    pub fn with_default_level(mut self, level: Level) -> Self {
+        self.default_level = level;
+        self
+    }
+
+    /// Writes through libtest's capturing, so output is only shown for failing tests
+    /// (or with `--nocapture`) and attributed to the test that emitted it.
+    pub fn with_test_writer(mut self) -> Self {
+        self.test_writer = true;
+        self
+    }
+
+    /// Installs the subscriber globally.
+    ///
+    /// Fails if a global subscriber has already been installed, e.g. by another test.
+    pub fn init(self) -> Result<(), TryInitError> {
+        tracing_subscriber::registry()
+            .with(self.fmt_layer())
+            .with(self.env_filter())
+            .try_init()
+    }
+
+    fn env_filter(&self) -> EnvFilter {
+        EnvFilter::builder()
+            .with_default_directive(self.default_level.into())
+            .from_env_lossy()
+    }
+
+    fn fmt_layer<S>(&self) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let writer = if self.test_writer {
+            BoxMakeWriter::new(TestWriter::default())
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        };
+        let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+        match self.format {
+            LogFormat::Pretty => layer.pretty().boxed(),
+            LogFormat::Compact => layer.compact().boxed(),
+            LogFormat::Json => layer
+                .json()
+                .with_current_span(true)
+                .with_ansi(false)
+                .boxed(),
+        }
+    }
+}
+
+/// Records all spans and events emitted while running a future, e.g. a test-body.
+///
+/// While capturing, spans and events are forwarded to the subscriber active when calling
+/// [`TracingCapture::capture`], e.g. the global one installed by [`TracingSetup::init`] or by the
+/// test itself, so nothing goes missing from the test-output.
+///
+/// Only the future itself is captured. Tasks it spawns, e.g. via `tokio::spawn`, run outside of it
+/// and only report to the global subscriber, unless wrapped with
+/// [`WithSubscriber::with_current_subscriber`].
+///
+/// ```no_run
+/// # async fn example() {
+/// use test_api::logging::{EventMatcher, TracingCapture};
+/// use tracing::Level;
+///
+/// let capture = TracingCapture::new();
+/// capture
+///     .capture(async { tracing::warn!(item_id = "X", "Dropping item") })
+///     .await;
+///
+/// capture.assert_emitted(&EventMatcher::at(Level::WARN).with_field("item_id", "X"));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TracingCapture {
+    records: Arc<Mutex<Records>>,
+}
+
+#[derive(Debug, Default)]
+struct Records {
+    events: Vec<CapturedEvent>,
+    spans: Vec<CapturedSpan>,
+}
+
+/// An event recorded by [`TracingCapture`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    pub level: Level,
+    pub target: String,
+    pub message: Option<String>,
+    /// All fields except `message`. String-values are recorded without quotes.
+    pub fields: BTreeMap<String, String>,
+    /// Names of the spans the event was emitted in, outermost first.
+    pub spans: Vec<String>,
+}
+
+/// A span recorded by [`TracingCapture`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedSpan {
+    pub name: String,
+    pub level: Level,
+    pub target: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl TracingCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `future` while recording everything it emits.
+    pub fn capture<F: Future>(&self, future: F) -> WithDispatch<F> {
+        future.with_subscriber(self.dispatch())
+    }
+
+    fn dispatch(&self) -> Dispatch {
+        let capture_layer = CaptureLayer {
+            records: self.records.clone(),
+        };
+        let forward_layer = ForwardLayer {
+            dispatch: tracing::dispatcher::get_default(Dispatch::clone),
+        };
+        tracing_subscriber::registry()
+            .with(capture_layer)
+            .with(forward_layer)
+            .into()
+    }
+
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.records().events.clone()
+    }
+
+    pub fn spans(&self) -> Vec<CapturedSpan> {
+        self.records().spans.clone()
+    }
+
+    /// Returns whether any captured event matches.
+    pub fn contains(&self, matcher: &EventMatcher) -> bool {
+        self.records()
+            .events
+            .iter()
+            .any(|event| matcher.matches(event))
+    }
+
+    /// Panics, listing all captured events, unless any captured event matches.
+    #[track_caller]
+    pub fn assert_emitted(&self, matcher: &EventMatcher) {
+        if !self.contains(matcher) {
+            panic!(
+                "expected an event matching {matcher} but captured:\n{}",
+                self.describe_events()
+            );
+        }
+    }
+
+    /// Panics, listing all captured events, if any captured event matches.
+    #[track_caller]
+    pub fn assert_not_emitted(&self, matcher: &EventMatcher) {
+        if self.contains(matcher) {
+            panic!(
+                "expected no event matching {matcher} but captured:\n{}",
+                self.describe_events()
+            );
+        }
+    }
+
+    fn describe_events(&self) -> String {
+        self.records()
+            .events
+            .iter()
+            .map(|event| format!("  {event:?}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn records(&self) -> std::sync::MutexGuard<'_, Records> {
+        self.records
+            .lock()
+            .expect("shouldn't fail locking captured records because no writer panics")
+    }
+}
+
+/// Describes an event to look for in a [`TracingCapture`].
+///
+/// Every criterion that is set has to match.
+#[derive(Debug, Clone, Default)]
+pub struct EventMatcher {
+    level: Option<Level>,
+    target: Option<String>,
+    message: Option<String>,
+    fields: BTreeMap<String, String>,
+    span: Option<String>,
+}
+
+impl EventMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches events at exactly `level`.
+    pub fn at(level: Level) -> Self {
+        Self::new().with_level(level)
+    }
+
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Matches events whose target starts with `target`, e.g. a module-path.
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn with_message_containing(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Matches events with a field `name` recorded as `value`.
+    pub fn with_field(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.fields.insert(name.into(), value.to_string());
+        self
+    }
+
+    /// Matches events emitted within a span named `name`.
+    pub fn in_span(mut self, name: impl Into<String>) -> Self {
+        self.span = Some(name.into());
+        self
+    }
+
+    pub fn matches(&self, event: &CapturedEvent) -> bool {
+        self.level.is_none_or(|level| event.level == level)
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| event.target.starts_with(target))
+            && self.message.as_ref().is_none_or(|message| {
+                event
+                    .message
+                    .as_ref()
+                    .is_some_and(|actual| actual.contains(message))
+            })
+            && self
+                .fields
+                .iter()
+                .all(|(name, value)| event.fields.get(name) == Some(value))
+            && self
+                .span
+                .as_ref()
+                .is_none_or(|span| event.spans.contains(span))
+    }
+}
+
+impl fmt::Display for EventMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+struct CaptureLayer {
+    records: Arc<Mutex<Records>>,
+}
+
+impl CaptureLayer {
+    fn records(&self) -> std::sync::MutexGuard<'_, Records> {
+        self.records
+            .lock()
+            .expect("shouldn't fail locking captured records because no writer panics")
+    }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        let metadata = attrs.metadata();
+        self.records().spans.push(CapturedSpan {
+            name: metadata.name().to_string(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            fields: visitor.fields,
+        });
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| span.name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let metadata = event.metadata();
+        self.records().events.push(CapturedEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+            spans,
+        });
+    }
+}
+
+/// Forwards spans and events to the dispatcher that was active before capturing.
+///
+/// Span-ids differ between both, so the forwarded id is kept in the span's extensions.
+struct ForwardLayer {
+    dispatch: Dispatch,
+}
+
+struct ForwardedId(Id);
+
+impl ForwardLayer {
+    fn forwarded_id<S>(id: &Id, ctx: &Context<'_, S>) -> Option<Id>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let span = ctx.span(id)?;
+        let extensions = span.extensions();
+        extensions
+            .get::<ForwardedId>()
+            .map(|forwarded| forwarded.0.clone())
+    }
+}
+
+impl<S> Layer<S> for ForwardLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        if !self.dispatch.enabled(metadata) {
+            return;
+        }
+        let explicit_parent = attrs
+            .parent()
+            .and_then(|parent| Self::forwarded_id(parent, &ctx));
+        let forwarded_attrs = match explicit_parent {
+            Some(parent) => Attributes::child_of(parent, metadata, attrs.values()),
+            None if attrs.is_contextual() => Attributes::new(metadata, attrs.values()),
+            None => Attributes::new_root(metadata, attrs.values()),
+        };
+        let forwarded_id = self.dispatch.new_span(&forwarded_attrs);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(ForwardedId(forwarded_id));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(forwarded_id) = Self::forwarded_id(id, &ctx) {
+            self.dispatch.record(&forwarded_id, values);
+        }
+    }
+
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: Context<'_, S>) {
+        if let Some(forwarded_id) = Self::forwarded_id(id, &ctx)
+            && let Some(forwarded_follows) = Self::forwarded_id(follows, &ctx)
+        {
+            self.dispatch
+                .record_follows_from(&forwarded_id, &forwarded_follows);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // The spans entered are mirrored, so the forwarded dispatcher finds the event's contextual
+        // parent itself.
+        if self.dispatch.enabled(event.metadata()) {
+            self.dispatch.event(event);
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(forwarded_id) = Self::forwarded_id(id, &ctx) {
+            self.dispatch.enter(&forwarded_id);
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(forwarded_id) = Self::forwarded_id(id, &ctx) {
+            self.dispatch.exit(&forwarded_id);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(forwarded_id) = Self::forwarded_id(&id, &ctx) {
+            self.dispatch.try_close(forwarded_id);
+        }
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}"));
+    }
+}
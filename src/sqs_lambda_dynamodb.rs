@@ -1,116 +1,198 @@
-use crate::localstack::{get_lambda_client, get_sqs_client, spin_up_localstack_with_services};
+use crate::artifact::{ArtifactSource, FileArtifact, HttpArtifact, fetch_artifact};
+use crate::error::{Error, Result};
+use crate::localstack::{
+    LocalStackBuilder, LocalStackInstance, Snapshot, get_lambda_client_in_region,
+    get_sqs_client_in_region,
+};
 use aws_sdk_lambda::client::Waiters;
+use aws_sdk_lambda::operation::create_function::CreateFunctionError;
 use aws_sdk_lambda::types::Runtime;
 use aws_sdk_sqs::types::QueueAttributeName;
 use aws_sdk_sqs::types::QueueAttributeName::QueueArn;
 use serde_json::json;
-use std::fs::File;
-use std::io::Read;
-use std::process::Command;
+use std::sync::OnceLock;
 use std::time::Duration;
-use testcontainers::ContainerAsync;
-use testcontainers_modules::localstack::LocalStack;
 use tokio::sync::OnceCell;
 
-static LOCALSTACK_SQS_LAMBDA_DYNAMODB: OnceCell<ContainerAsync<LocalStack>> = OnceCell::const_new();
+/// Name of the container of [`localstack_builder`].
+pub const CONTAINER_NAME: &str = "localstack-test-api-sqs-lambda-dynamodb";
 
-/// Lazily initializes and returns a shared Localstack container running:
+/// Name under which this environment is recorded once provisioned in a Localstack.
+pub const ENVIRONMENT: &str = "sqs_lambda_dynamodb";
+
+static LOCALSTACK_SQS_LAMBDA_DYNAMODB: OnceCell<LocalStackInstance> = OnceCell::const_new();
+
+/// Lazily initializes and returns a shared Localstack running:
 /// - SQS collecting items to write
 /// - Lambda consuming items from the SQS and writing them to
 /// - DynamoDB
-pub async fn get_localstack_sqs_lambda_dynamodb() -> &'static ContainerAsync<LocalStack> {
+///
+/// If initialization fails, the next call tries again. The resources are provisioned via
+/// [`LocalStackInstance::provision_environment`].
+pub async fn get_localstack_sqs_lambda_dynamodb() -> Result<&'static LocalStackInstance> {
     LOCALSTACK_SQS_LAMBDA_DYNAMODB
-        .get_or_init(|| async {
-            let ls = spin_up_localstack_with_services(&["sqs", "lambda", "dynamodb"]).await;
-            init().await;
-            ls
+        .get_or_try_init(|| async {
+            let localstack = localstack_builder().start().await?;
+            localstack
+                .provision_environment(ENVIRONMENT, with_fingerprint, || init(&localstack))
+                .await?;
+            Ok(localstack)
         })
         .await
 }
 
+/// Version of what [`init`] provisions besides the DynamoDB-environment and the Lambda's
+/// `bootstrap.zip`.
+///
+/// Bump it when changing how the queue or Lambda are created, so existing snapshots are taken again.
+const PROVISIONING_VERSION: &str = "1";
+
+/// Adds everything [`init`] provisions to the fingerprint of `snapshot`.
+///
+/// Covers the content of the Lambda's `bootstrap.zip`, so a new release replaces the snapshot.
+async fn with_fingerprint(snapshot: Snapshot) -> Result<Snapshot> {
+    Ok(crate::dynamodb::with_fingerprint(snapshot)
+        .with_fingerprint(PROVISIONING_VERSION)
+        .with_fingerprint(lambda_bootstrap().await?))
+}
+
+/// Describes the Localstack of [`get_localstack_sqs_lambda_dynamodb`].
+pub fn localstack_builder() -> LocalStackBuilder {
+    LocalStackBuilder::new()
+        .with_services(["sqs", "lambda", "dynamodb"])
+        .with_container_name(CONTAINER_NAME)
+        .with_reuse_per_test_process()
+}
+
 pub const LAMBDA_NAME: &str = "item-write-lambda";
-const LAMBDA_BOOTSRAP_ZIP_PATH: &str = "/tmp/item-write-lambda_bootstrap.zip";
-
-pub async fn init() {
-    let lambda_client = get_lambda_client().await;
-    let sqs_client = get_sqs_client().await;
-    set_up_queues(sqs_client).await.expect(&format!(
-        "shouldn't fail creating queue '{WRITE_LAMBDA_QUEUE_NAME}'"
-    ));
-    set_up_lambda(lambda_client)
+pub const LAMBDA_BOOTSTRAP_ZIP_URL: &str =
+    "https://raw.githubusercontent.com/blitzfilter/item-write-lambda/main/bootstrap.zip";
+/// If set, the Lambda's `bootstrap.zip` is read from this path instead of being downloaded from
+/// [`LAMBDA_BOOTSTRAP_ZIP_URL`].
+pub const LAMBDA_BOOTSTRAP_ZIP_ENV_VAR: &str = "TEST_API_LAMBDA_BOOTSTRAP_ZIP";
+const LAMBDA_ACTIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+static LAMBDA_BOOTSTRAP_SOURCE: OnceLock<Box<dyn ArtifactSource>> = OnceLock::new();
+
+/// Sets where the Lambda's `bootstrap.zip` comes from.
+///
+/// Must be called before the Lambda is set up, i.e. before the first call of
+/// [`get_localstack_sqs_lambda_dynamodb`] or [`init`]. Defaults to
+/// [`LAMBDA_BOOTSTRAP_ZIP_ENV_VAR`] if set, otherwise [`LAMBDA_BOOTSTRAP_ZIP_URL`].
+pub fn set_lambda_bootstrap_source(source: impl ArtifactSource + 'static) -> Result<()> {
+    LAMBDA_BOOTSTRAP_SOURCE
+        .set(Box::new(source))
+        .map_err(|_| Error::Config("the Lambda's bootstrap-source is already set".to_string()))
+}
+
+fn lambda_bootstrap_source() -> &'static dyn ArtifactSource {
+    LAMBDA_BOOTSTRAP_SOURCE
+        .get_or_init(|| match std::env::var_os(LAMBDA_BOOTSTRAP_ZIP_ENV_VAR) {
+            Some(path) => Box::new(FileArtifact::new(path)),
+            None => Box::new(HttpArtifact::new(LAMBDA_BOOTSTRAP_ZIP_URL)),
+        })
+        .as_ref()
+}
+
+static LAMBDA_BOOTSTRAP: OnceCell<Vec<u8>> = OnceCell::const_new();
+
+/// Fetches the Lambda's `bootstrap.zip` from [`lambda_bootstrap_source`] once per process.
+async fn lambda_bootstrap() -> Result<&'static [u8]> {
+    LAMBDA_BOOTSTRAP
+        .get_or_try_init(|| fetch_artifact(lambda_bootstrap_source()))
         .await
-        .expect(&format!("shouldn't fail setting up lambda '{LAMBDA_NAME}'"));
+        .map(Vec::as_slice)
+}
+
+/// Sets up queues, Lambda and tables in the region of `localstack`.
+///
+/// Resources that already exist are left as they are, so this can run against a reused
+/// Localstack.
+pub async fn init(localstack: &LocalStackInstance) -> Result<()> {
+    init_in_regions(localstack, &[localstack.region()]).await
+}
+
+/// Sets up queues, Lambda and tables in each of `regions`.
+///
+/// Queue-URLs don't depend on the region, so the queues of a region are addressed through
+/// clients for that region, e.g. [`get_sqs_client_in_region`].
+pub async fn init_in_regions(localstack: &LocalStackInstance, regions: &[&str]) -> Result<()> {
+    for region in regions {
+        init_region(localstack, region).await?;
+    }
+    crate::dynamodb::init_in_regions(localstack, regions).await
+}
+
+async fn init_region(localstack: &LocalStackInstance, region: &str) -> Result<()> {
+    let lambda_client = &get_lambda_client_in_region(localstack, region);
+    let sqs_client = &get_sqs_client_in_region(localstack, region);
+    let queue_url = get_write_lambda_queue_url(localstack);
+    let dlq_url = get_write_lambda_queue_dlq_url(localstack);
+    set_up_queues(sqs_client, &dlq_url).await?;
+    set_up_lambda(
+        lambda_client,
+        &localstack.global_arn("iam", "role/service-role/dummy"),
+    )
+    .await?;
     lambda_client
         .wait_until_function_active_v2()
         .function_name(LAMBDA_NAME)
-        .wait(Duration::from_secs(30))
+        .wait(LAMBDA_ACTIVE_TIMEOUT)
         .await
-        .expect("shouldn't fail waiting until lambda is active");
-    setup_sqs_lambda_config(sqs_client, lambda_client)
-        .await
-        .expect("shouldn't fail setting up sqs lambda config");
-    crate::dynamodb::init().await;
-}
-
-async fn set_up_lambda(client: &aws_sdk_lambda::Client) -> Result<(), Box<dyn std::error::Error>> {
-    Command::new("wget")
-        .args([
-            "--no-check-certificate",
-            "https://raw.githubusercontent.com/blitzfilter/item-write-lambda/main/bootstrap.zip",
-            "-O",
-            LAMBDA_BOOTSRAP_ZIP_PATH,
-        ])
-        .output()
-        .expect(&format!(
-            "shouldn't fail downloading bootstrap-zip for '{LAMBDA_NAME}'"
-        ));
-
-    let mut file = File::open(LAMBDA_BOOTSRAP_ZIP_PATH).expect(&format!(
-        "shouldn't fail opening '{LAMBDA_BOOTSRAP_ZIP_PATH}'"
-    ));
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).expect(&format!(
-        "shouldn't fail reading '{LAMBDA_BOOTSRAP_ZIP_PATH}'"
-    ));
-
-    client
+        .map_err(|e| Error::Timeout {
+            operation: format!("waiting until lambda '{LAMBDA_NAME}' is active"),
+            timeout: LAMBDA_ACTIVE_TIMEOUT,
+            source: e.into(),
+        })?;
+    setup_sqs_lambda_config(sqs_client, lambda_client, &queue_url).await
+}
+
+async fn set_up_lambda(client: &aws_sdk_lambda::Client, role_arn: &str) -> Result<()> {
+    let buffer = lambda_bootstrap().await?.to_vec();
+
+    let created = client
         .create_function()
         .function_name(LAMBDA_NAME)
         .runtime(Runtime::Providedal2023)
         .handler("lib.function_handler")
-        .role("arn:aws:iam::000000000000:role/service-role/dummy")
+        .role(role_arn)
         .code(
             aws_sdk_lambda::types::FunctionCode::builder()
                 .zip_file(buffer.into())
                 .build(),
         )
         .send()
-        .await
-        .expect("shouldn't fail creating lambda");
-
-    Ok(())
+        .await;
+    match created {
+        Ok(_) => Ok(()),
+        // Already set up, e.g. in a reused Localstack.
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(CreateFunctionError::is_resource_conflict_exception) =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(aws_sdk_lambda::Error::from(e).into()),
+    }
 }
 
 async fn setup_sqs_lambda_config(
     sqs_client: &aws_sdk_sqs::Client,
     lambda_client: &aws_sdk_lambda::Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let q_arn = sqs_client
-        .get_queue_attributes()
-        .queue_url(WRITE_LAMBDA_QUEUE_URL)
-        .attribute_names(QueueArn)
+    queue_url: &str,
+) -> Result<()> {
+    let q_arn = get_queue_arn(sqs_client, queue_url).await?;
+
+    let existing_mappings = lambda_client
+        .list_event_source_mappings()
+        .function_name(LAMBDA_NAME)
+        .event_source_arn(&q_arn)
         .send()
         .await
-        .expect(&format!(
-            "shouldn't fail retrieving ARN for queue '{WRITE_LAMBDA_QUEUE_NAME}' with url '{WRITE_LAMBDA_QUEUE_URL}'"
-        ))
-        .attributes
-        .expect("shouldn't fail getting queue attributes because we explicitly requested some")
-        .get(&QueueArn)
-        .expect(&format!(
-            "shouldn't fail getting queue attribute '{QueueArn}' because we explicitly requested it"
-        ))
-        .to_string();
+        .map_err(aws_sdk_lambda::Error::from)?;
+    if !existing_mappings.event_source_mappings().is_empty() {
+        return Ok(());
+    }
 
     lambda_client
         .create_event_source_mapping()
@@ -120,45 +202,53 @@ async fn setup_sqs_lambda_config(
         .maximum_batching_window_in_seconds(5)
         .send()
         .await
-        .expect("shouldn't fail creating event source mapping");
+        .map_err(aws_sdk_lambda::Error::from)?;
 
     Ok(())
 }
 
+async fn get_queue_arn(sqs_client: &aws_sdk_sqs::Client, queue_url: &str) -> Result<String> {
+    sqs_client
+        .get_queue_attributes()
+        .queue_url(queue_url)
+        .attribute_names(QueueArn)
+        .send()
+        .await
+        .map_err(aws_sdk_sqs::Error::from)?
+        .attributes
+        .and_then(|mut attributes| attributes.remove(&QueueArn))
+        .ok_or_else(|| Error::UnexpectedResponse {
+            operation: format!("reading the ARN of queue '{queue_url}'"),
+            reason: format!("lacks the attribute '{QueueArn}'"),
+        })
+}
+
 pub const WRITE_LAMBDA_QUEUE_NAME: &str = "write_lambda_queue";
-pub const WRITE_LAMBDA_QUEUE_URL: &str =
-    "http://sqs.eu-central-1.localhost.localstack.cloud:4566/000000000000/write_lambda_queue";
 pub const WRITE_LAMBDA_QUEUE_DLQ_NAME: &str = "write_lambda_queue_dlq";
-pub const WRITE_LAMBDA_QUEUE_DLQ_URL: &str =
-    "http://sqs.eu-central-1.localhost.localstack.cloud:4566/000000000000/write_lambda_queue_dlq";
 
-pub async fn setup(dynamodb_client: &aws_sdk_dynamodb::Client) {
-    crate::dynamodb::setup(dynamodb_client).await;
+/// Returns the URL of the queue the Lambda consumes items from.
+pub fn get_write_lambda_queue_url(localstack: &LocalStackInstance) -> String {
+    localstack.queue_url(WRITE_LAMBDA_QUEUE_NAME)
+}
+
+/// Returns the URL of the dead-letter-queue of [`WRITE_LAMBDA_QUEUE_NAME`].
+pub fn get_write_lambda_queue_dlq_url(localstack: &LocalStackInstance) -> String {
+    localstack.queue_url(WRITE_LAMBDA_QUEUE_DLQ_NAME)
 }
 
-async fn set_up_queues(sqs_client: &aws_sdk_sqs::Client) -> Result<(), aws_sdk_sqs::Error> {
+pub async fn setup(dynamodb_client: &aws_sdk_dynamodb::Client) -> Result<()> {
+    crate::dynamodb::setup(dynamodb_client).await
+}
+
+async fn set_up_queues(sqs_client: &aws_sdk_sqs::Client, dlq_url: &str) -> Result<()> {
     sqs_client
         .create_queue()
         .queue_name(WRITE_LAMBDA_QUEUE_DLQ_NAME)
         .send()
-        .await?;
-
-    let dlq_arn = sqs_client
-        .get_queue_attributes()
-        .queue_url(WRITE_LAMBDA_QUEUE_DLQ_URL)
-        .attribute_names(QueueArn)
-        .send()
         .await
-        .expect(&format!(
-            "shouldn't fail retrieving ARN for queue '{WRITE_LAMBDA_QUEUE_DLQ_NAME}' with url '{WRITE_LAMBDA_QUEUE_DLQ_URL}'"
-        ))
-        .attributes
-        .expect("shouldn't fail getting queue attributes because we explicitly requested some")
-        .get(&QueueArn)
-        .expect(&format!(
-            "shouldn't fail getting queue attribute '{QueueArn}' because we explicitly requested it"
-        ))
-        .to_string();
+        .map_err(aws_sdk_sqs::Error::from)?;
+
+    let dlq_arn = get_queue_arn(sqs_client, dlq_url).await?;
 
     sqs_client
         .create_queue()
@@ -168,18 +258,24 @@ async fn set_up_queues(sqs_client: &aws_sdk_sqs::Client) -> Result<(), aws_sdk_s
             json!({"deadLetterTargetArn": dlq_arn, "maxReceiveCount": 5}).to_string(),
         )
         .send()
-        .await?;
+        .await
+        .map_err(aws_sdk_sqs::Error::from)?;
     Ok(())
 }
 
-pub async fn reset(sqs_client: &aws_sdk_sqs::Client, dynamodb_client: &aws_sdk_dynamodb::Client) {
-    crate::dynamodb::reset(dynamodb_client).await;
+/// Purges the queue and restores DynamoDB to its initial state, see [`crate::dynamodb::reset`].
+///
+/// The queue is purged first, so the Lambda doesn't write pending items after DynamoDB was reset.
+pub async fn reset(
+    localstack: &LocalStackInstance,
+    sqs_client: &aws_sdk_sqs::Client,
+    dynamodb_client: &aws_sdk_dynamodb::Client,
+) -> Result<()> {
     sqs_client
         .purge_queue()
-        .queue_url(WRITE_LAMBDA_QUEUE_URL)
+        .queue_url(get_write_lambda_queue_url(localstack))
         .send()
         .await
-        .expect(&format!(
-            "shouldn't fail purging queue '{WRITE_LAMBDA_QUEUE_NAME}'"
-        ));
+        .map_err(aws_sdk_sqs::Error::from)?;
+    crate::dynamodb::reset(dynamodb_client).await
 }
@@ -12,15 +12,34 @@ pub fn blitzfilter_dynamodb_test(_attr: TokenStream, item: TokenStream) -> Token
         #[tokio::test]
         #[test_api::serial_test::serial]
         async fn #fn_name() {
-            let container = test_api::dynamodb::get_localstack_dynamodb().await;
-            let client = test_api::localstack::get_dynamodb_client().await;
+            let test_name = concat!(module_path!(), "::", stringify!(#fn_name));
+            let localstack = match test_api::dynamodb::get_localstack_dynamodb().await {
+                Ok(localstack) => localstack,
+                Err(e) if e.should_skip_test() => {
+                    eprintln!("Skipping '{test_name}': {e}");
+                    return;
+                }
+                Err(e) => e.dump_logs_and_panic(
+                    test_name,
+                    "shouldn't fail initializing Localstack running DynamoDB",
+                ),
+            };
+            let _exclusive_use = localstack.exclusive_use().await.unwrap_or_else(|e| {
+                e.dump_logs_and_panic(test_name, "shouldn't fail waiting for exclusive use of Localstack")
+            });
+            let _dump_logs_on_panic =
+                test_api::localstack::DumpLogsOnPanic::new(test_name, localstack);
+            let client = &test_api::localstack::get_dynamodb_client(localstack);
 
-            test_api::dynamodb::setup(client).await;
+            // Resetting before instead of after the test also recovers from a previous test that
+            // panicked, e.g. in another process sharing the Localstack.
+            test_api::dynamodb::reset(client)
+                .await
+                .expect("shouldn't fail resetting DynamoDB");
 
+            let tracing_capture = test_api::logging::TracingCapture::new();
             let test_fn = async #fn_block;
-            test_fn.await;
-
-            test_api::dynamodb::reset(client).await;
+            tracing_capture.capture(test_fn).await;
         }
     };
 
@@ -37,16 +56,35 @@ pub fn blitzfilter_data_ingestion_test(_attr: TokenStream, item: TokenStream) ->
         #[tokio::test]
         #[test_api::serial_test::serial]
         async fn #fn_name() {
-            let container = test_api::sqs_lambda_dynamodb::get_localstack_sqs_lambda_dynamodb().await;
-            let dynamodb_client = test_api::localstack::get_dynamodb_client().await;
-            let sqs_client = test_api::localstack::get_sqs_client().await;
+            let test_name = concat!(module_path!(), "::", stringify!(#fn_name));
+            let localstack = match test_api::sqs_lambda_dynamodb::get_localstack_sqs_lambda_dynamodb().await {
+                Ok(localstack) => localstack,
+                Err(e) if e.should_skip_test() => {
+                    eprintln!("Skipping '{test_name}': {e}");
+                    return;
+                }
+                Err(e) => e.dump_logs_and_panic(
+                    test_name,
+                    "shouldn't fail initializing Localstack running SQS, Lambda and DynamoDB",
+                ),
+            };
+            let _exclusive_use = localstack.exclusive_use().await.unwrap_or_else(|e| {
+                e.dump_logs_and_panic(test_name, "shouldn't fail waiting for exclusive use of Localstack")
+            });
+            let _dump_logs_on_panic =
+                test_api::localstack::DumpLogsOnPanic::new(test_name, localstack);
+            let dynamodb_client = &test_api::localstack::get_dynamodb_client(localstack);
+            let sqs_client = &test_api::localstack::get_sqs_client(localstack);
 
-            test_api::sqs_lambda_dynamodb::setup(dynamodb_client).await;
+            // Resetting before instead of after the test also recovers from a previous test that
+            // panicked, e.g. in another process sharing the Localstack.
+            test_api::sqs_lambda_dynamodb::reset(localstack, sqs_client, dynamodb_client)
+                .await
+                .expect("shouldn't fail resetting SQS and DynamoDB");
 
+            let tracing_capture = test_api::logging::TracingCapture::new();
             let test_fn = async #fn_block;
-            test_fn.await;
-
-            test_api::sqs_lambda_dynamodb::reset(sqs_client, dynamodb_client).await;
+            tracing_capture.capture(test_fn).await;
         }
     };
 
@@ -0,0 +1,32 @@
+use test_api::Error;
+use test_api::artifact::{FileArtifact, InlineArtifact, fetch_artifact};
+
+#[tokio::test]
+async fn should_fetch_file_artifact() {
+    let path = std::env::temp_dir().join("test-api-should_fetch_file_artifact.zip");
+    std::fs::write(&path, b"bootstrap").unwrap();
+
+    let artifact = fetch_artifact(&FileArtifact::new(&path)).await;
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(artifact.unwrap(), b"bootstrap");
+}
+
+#[tokio::test]
+async fn should_fetch_inline_artifact() {
+    let artifact = fetch_artifact(&InlineArtifact::new(b"bootstrap".as_slice())).await;
+
+    assert_eq!(artifact.unwrap(), b"bootstrap");
+}
+
+#[tokio::test]
+async fn should_name_location_of_missing_artifact() {
+    let artifact = fetch_artifact(&FileArtifact::new("/nonexistent/bootstrap.zip")).await;
+
+    match artifact {
+        Err(Error::ArtifactFetch { artifact, .. }) => {
+            assert_eq!(artifact, "/nonexistent/bootstrap.zip");
+        }
+        other => panic!("expected Error::ArtifactFetch but got {other:?}"),
+    }
+}
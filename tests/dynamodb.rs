@@ -1,12 +1,18 @@
 use aws_sdk_dynamodb::types::AttributeValue::S;
+use aws_sdk_dynamodb::types::KeyType;
+use serial_test::serial;
 use std::collections::HashMap;
-use test_api::localstack::get_dynamodb_client;
+use test_api::dynamodb::ResetStrategy;
+use test_api::dynamodb::drift::{Drift, detect_drift};
+use test_api::dynamodb::spec::{AttributeType, Projection};
+use test_api::localstack::{get_dynamodb_client, get_dynamodb_client_in_region};
+use test_api::logging::EventMatcher;
 use test_api_macros::blitzfilter_dynamodb_test;
+use tracing::Level;
 
 #[blitzfilter_dynamodb_test]
 async fn should_set_up_tables_for_setup() {
-    let list_tables_output = get_dynamodb_client()
-        .await
+    let list_tables_output = get_dynamodb_client(localstack)
         .list_tables()
         .send()
         .await
@@ -22,8 +28,7 @@ async fn should_set_up_tables_for_setup() {
 
 #[blitzfilter_dynamodb_test]
 async fn should_insert_test_items_for_setup() {
-    let scan_output = get_dynamodb_client()
-        .await
+    let scan_output = get_dynamodb_client(localstack)
         .scan()
         .table_name("items")
         .send()
@@ -34,8 +39,15 @@ async fn should_insert_test_items_for_setup() {
 }
 
 #[blitzfilter_dynamodb_test]
-async fn should_drop_test_items_for_reset() {
-    let client = get_dynamodb_client().await;
+async fn should_skip_existing_tables_for_repeated_init() {
+    test_api::dynamodb::init(localstack).await.unwrap();
+
+    let list_tables_output = client.list_tables().send().await.unwrap();
+    assert_eq!(list_tables_output.table_names().len(), 3);
+}
+
+#[blitzfilter_dynamodb_test]
+async fn should_restore_test_items_for_reset() {
     client
         .put_item()
         .table_name("items")
@@ -50,8 +62,139 @@ async fn should_drop_test_items_for_reset() {
     let scan_output_pre_reset = client.scan().table_name("items").send().await.ok().unwrap();
     assert_eq!(scan_output_pre_reset.count, 26);
 
-    test_api::dynamodb::reset(client).await;
+    test_api::dynamodb::reset(client).await.unwrap();
 
     let scan_output_post_reset = client.scan().table_name("items").send().await.ok().unwrap();
-    assert_eq!(scan_output_post_reset.count, 0);
+    assert_eq!(scan_output_post_reset.count, 25);
+    let item = client
+        .get_item()
+        .table_name("items")
+        .set_key(Some(HashMap::from([
+            ("pk".to_string(), S("item#123456".to_string())),
+            ("sk".to_string(), S("item#abcdef".to_string())),
+        ])))
+        .send()
+        .await
+        .unwrap();
+    assert!(item.item.is_none());
+}
+
+#[blitzfilter_dynamodb_test]
+async fn should_restore_dropped_tables_for_recreating_reset() {
+    client
+        .delete_table()
+        .table_name("filters")
+        .send()
+        .await
+        .unwrap();
+
+    test_api::dynamodb::reset_with(client, ResetStrategy::Recreate)
+        .await
+        .unwrap();
+
+    test_api::dynamodb::drift::assert_no_drift(client).await;
+    let scan_output = client.scan().table_name("items").send().await.unwrap();
+    assert_eq!(scan_output.count, 25);
+    let query_output = client
+        .query()
+        .table_name("items")
+        .index_name("gsi_1_hash_index")
+        .key_condition_expression("party_id = :party_id")
+        .expression_attribute_values(":party_id", S("source#https://a1militaria.com".to_string()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(query_output.count, 11);
+}
+
+#[blitzfilter_dynamodb_test]
+async fn should_capture_tracing_events_of_test_body() {
+    tracing::warn!(item_id = "item#123456", "Dropping item");
+
+    tracing_capture
+        .assert_emitted(&EventMatcher::at(Level::WARN).with_field("item_id", "item#123456"));
+}
+
+#[serial]
+#[tokio::test]
+async fn should_set_up_tables_in_every_region() {
+    let localstack = test_api::dynamodb::localstack_builder()
+        .with_container_name("localstack-test-api-multi-region")
+        .start()
+        .await
+        .unwrap();
+
+    test_api::dynamodb::init_in_regions(&localstack, &["eu-central-1", "us-east-1"])
+        .await
+        .unwrap();
+
+    for region in ["eu-central-1", "us-east-1"] {
+        let list_tables_output = get_dynamodb_client_in_region(&localstack, region)
+            .list_tables()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(list_tables_output.table_names().len(), 3);
+    }
+    let list_tables_output = get_dynamodb_client_in_region(&localstack, "ap-south-1")
+        .list_tables()
+        .send()
+        .await
+        .unwrap();
+    assert!(list_tables_output.table_names().is_empty());
+
+    drop(localstack);
+}
+
+#[blitzfilter_dynamodb_test]
+async fn should_report_no_drift_of_registered_tables() {
+    test_api::dynamodb::drift::assert_no_drift(client).await;
+}
+
+#[blitzfilter_dynamodb_test]
+async fn should_report_drift_from_changed_specs() {
+    let mut specs = test_api::dynamodb::default_table_specs();
+    specs[1].global_secondary_indexes[0].projection = Projection::KeysOnly;
+    specs[2].global_secondary_indexes.clear();
+    specs[2].range_key = None;
+    specs[2]
+        .attributes
+        .insert("sk".to_string(), AttributeType::N);
+    specs[0].table_name = "customers".to_string();
+
+    let report = detect_drift(client, &specs).await.unwrap();
+
+    assert_eq!(
+        report.drifts,
+        [
+            Drift::MissingTable {
+                table: "customers".to_string()
+            },
+            Drift::WrongProjection {
+                table: "items".to_string(),
+                index: "gsi_1_hash_index".to_string(),
+                expected: Projection::KeysOnly,
+                actual: Some(Projection::Include {
+                    non_key_attributes: vec!["hash".to_string()]
+                }),
+            },
+            Drift::WrongKey {
+                table: "filters".to_string(),
+                index: None,
+                key_type: KeyType::Range,
+                expected: None,
+                actual: Some("sk".to_string()),
+            },
+            Drift::WrongAttributeType {
+                table: "filters".to_string(),
+                attribute: "sk".to_string(),
+                expected: AttributeType::N,
+                actual: Some(AttributeType::S),
+            },
+            Drift::UnexpectedIndex {
+                table: "filters".to_string(),
+                index: "gsi_1_inverted_keys".to_string(),
+            },
+        ]
+    );
 }
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use test_api::dynamodb::cloudformation::CloudFormationTemplate;
+use test_api::dynamodb::default_table_specs;
+use test_api::dynamodb::spec::{AttributeType, BillingMode, StreamViewType, Throughput};
+
+const TEMPLATE: &str = r#"
+AWSTemplateFormatVersion: "2010-09-09"
+Transform: AWS::Serverless-2016-10-31
+Parameters:
+  Stage:
+    Type: String
+  Capacity:
+    Type: Number
+    Default: 5
+Conditions:
+  IsProd: !Equals [!Ref Stage, prod]
+Resources:
+  ItemsTable:
+    Type: AWS::DynamoDB::Table
+    Properties:
+      TableName: items
+      BillingMode: PAY_PER_REQUEST
+      AttributeDefinitions:
+        - AttributeName: pk
+          AttributeType: S
+        - AttributeName: sk
+          AttributeType: S
+        - AttributeName: party_id
+          AttributeType: S
+        - AttributeName: event_id
+          AttributeType: S
+      KeySchema:
+        - AttributeName: pk
+          KeyType: HASH
+        - AttributeName: sk
+          KeyType: RANGE
+      GlobalSecondaryIndexes:
+        - IndexName: gsi_1_hash_index
+          KeySchema:
+            - AttributeName: party_id
+              KeyType: HASH
+            - AttributeName: event_id
+              KeyType: RANGE
+          Projection:
+            ProjectionType: INCLUDE
+            NonKeyAttributes: [hash]
+  EventsTable:
+    Type: AWS::DynamoDB::Table
+    Properties:
+      TableName: !Sub "${Stage}-events"
+      AttributeDefinitions:
+        - AttributeName: id
+          AttributeType: N
+      KeySchema:
+        - AttributeName: id
+          KeyType: HASH
+      ProvisionedThroughput:
+        ReadCapacityUnits: !Ref Capacity
+        WriteCapacityUnits: "2"
+      TimeToLiveSpecification:
+        AttributeName: expires_at
+        Enabled: true
+      StreamSpecification:
+        StreamViewType: NEW_IMAGE
+  SessionsTable:
+    Type: AWS::Serverless::SimpleTable
+    Properties:
+      PrimaryKey:
+        Name: session_id
+        Type: String
+  ItemsQueue:
+    Type: AWS::SQS::Queue
+    Properties:
+      QueueName: !GetAtt ItemsTable.Arn
+"#;
+
+#[test]
+fn should_derive_table_specs_from_yaml_template() {
+    let specs = CloudFormationTemplate::parse(TEMPLATE)
+        .unwrap()
+        .with_parameter("Stage", "test")
+        .table_specs()
+        .unwrap();
+
+    let table_names = specs
+        .iter()
+        .map(|spec| spec.table_name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(table_names, ["test-events", "items", "SessionsTable"]);
+
+    let events = &specs[0];
+    assert_eq!(
+        events.attributes,
+        BTreeMap::from([("id".to_string(), AttributeType::N)])
+    );
+    assert_eq!(events.billing_mode, BillingMode::Provisioned);
+    assert_eq!(
+        events.provisioned_throughput,
+        Some(Throughput {
+            read_capacity_units: 5,
+            write_capacity_units: 2
+        })
+    );
+    assert_eq!(events.time_to_live_attribute.as_deref(), Some("expires_at"));
+    assert_eq!(events.stream_view_type, Some(StreamViewType::NewImage));
+
+    assert_eq!(specs[1], default_table_specs().remove(1));
+
+    let sessions = &specs[2];
+    assert_eq!(sessions.hash_key, "session_id");
+    assert_eq!(sessions.billing_mode, BillingMode::PayPerRequest);
+}
+
+#[test]
+fn should_derive_table_specs_from_json_template() {
+    let template = r#"{
+        "Resources": {
+            "Table": {
+                "Type": "AWS::DynamoDB::Table",
+                "Properties": {
+                    "TableName": {"Fn::Join": ["-", [{"Ref": "AWS::StackName"}, "parties"]]},
+                    "BillingMode": "PAY_PER_REQUEST",
+                    "AttributeDefinitions": [{"AttributeName": "pk", "AttributeType": "S"}],
+                    "KeySchema": [{"AttributeName": "pk", "KeyType": "HASH"}]
+                }
+            }
+        }
+    }"#;
+
+    let specs = CloudFormationTemplate::parse(template)
+        .unwrap()
+        .with_parameter("AWS::StackName", "stack")
+        .table_specs()
+        .unwrap();
+
+    let mut expected = default_table_specs().remove(0);
+    expected.table_name = "stack-parties".to_string();
+    assert_eq!(specs, [expected]);
+}
+
+#[test]
+fn should_fail_for_unresolvable_parameter() {
+    let result = CloudFormationTemplate::parse(TEMPLATE)
+        .unwrap()
+        .table_specs();
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("EventsTable"), "unexpected error: {error}");
+    assert!(error.contains("'Stage'"), "unexpected error: {error}");
+}
+
+#[test]
+fn should_ignore_unsupported_intrinsics_in_unused_properties() {
+    let template = r#"
+Resources:
+  ItemsTable:
+    Type: AWS::DynamoDB::Table
+    Properties:
+      TableName: items
+      AttributeDefinitions:
+        - AttributeName: pk
+          AttributeType: S
+      KeySchema:
+        - AttributeName: pk
+          KeyType: HASH
+      SSESpecification:
+        SSEEnabled: true
+        KMSMasterKeyId: !Ref KmsKey
+      PointInTimeRecoverySpecification:
+        PointInTimeRecoveryEnabled: !If [IsProd, true, false]
+      Tags:
+        - Key: queue
+          Value: !GetAtt ItemsQueue.Arn
+"#;
+
+    let specs = CloudFormationTemplate::parse(template)
+        .unwrap()
+        .table_specs()
+        .unwrap();
+
+    assert_eq!(specs.len(), 1);
+    assert_eq!(specs[0].table_name, "items");
+    assert_eq!(specs[0].hash_key, "pk");
+}
@@ -0,0 +1,206 @@
+use aws_sdk_dynamodb::types::{AttributeValue, ProjectionType, StreamViewType, TimeToLiveStatus};
+use serial_test::serial;
+use std::collections::BTreeMap;
+use test_api::dynamodb::spec::{AttributeType, IndexSpec, Projection};
+use test_api::dynamodb::{
+    ResetStrategy, TableSpec, TableSpecs, create_table, default_table_specs, register_table_spec,
+    registered_table_specs, reset_with, unregister_table_spec,
+};
+use test_api::localstack::{LocalStackBuilder, get_dynamodb_client};
+
+fn orders_spec() -> TableSpec {
+    TableSpec {
+        table_name: "orders".to_string(),
+        attributes: BTreeMap::from([
+            ("pk".to_string(), AttributeType::S),
+            ("created_at".to_string(), AttributeType::N),
+        ]),
+        hash_key: "pk".to_string(),
+        range_key: Some("created_at".to_string()),
+        local_secondary_indexes: vec![IndexSpec {
+            index_name: "lsi_1_created_at".to_string(),
+            hash_key: "pk".to_string(),
+            range_key: Some("created_at".to_string()),
+            projection: Projection::KeysOnly,
+            provisioned_throughput: None,
+        }],
+        time_to_live_attribute: Some("expires_at".to_string()),
+        stream_view_type: Some(test_api::dynamodb::spec::StreamViewType::NewAndOldImages),
+        ..TableSpec::default()
+    }
+}
+
+#[test]
+fn should_provide_default_table_specs() {
+    let specs = default_table_specs();
+
+    let table_names = specs
+        .iter()
+        .map(|spec| spec.table_name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(table_names, ["parties", "items", "filters"]);
+    assert_eq!(
+        specs[1].global_secondary_indexes[0].projection,
+        Projection::Include {
+            non_key_attributes: vec!["hash".to_string()]
+        }
+    );
+    assert_eq!(
+        specs[2].global_secondary_indexes[0].projection,
+        Projection::KeysOnly
+    );
+}
+
+#[test]
+fn should_parse_toml_and_json_alike() {
+    let toml = r#"
+        [[tables]]
+        table_name = "orders"
+        attributes = { pk = "S", created_at = "N" }
+        hash_key = "pk"
+        range_key = "created_at"
+        time_to_live_attribute = "expires_at"
+        stream_view_type = "NEW_AND_OLD_IMAGES"
+
+        [[tables.local_secondary_indexes]]
+        index_name = "lsi_1_created_at"
+        hash_key = "pk"
+        range_key = "created_at"
+        projection = { type = "KEYS_ONLY" }
+    "#;
+    let json = serde_json::to_string(&TableSpecs {
+        tables: vec![orders_spec()],
+    })
+    .unwrap();
+
+    assert_eq!(TableSpecs::from_toml(toml).unwrap().tables, [orders_spec()]);
+    assert_eq!(
+        TableSpecs::from_json(&json).unwrap().tables,
+        [orders_spec()]
+    );
+}
+
+#[test]
+fn should_reject_unknown_attribute_type() {
+    let json = r#"{"tables": [{"table_name": "t", "attributes": {"pk": "X"}, "hash_key": "pk"}]}"#;
+
+    assert!(TableSpecs::from_json(json).is_err());
+}
+
+/// Restores the registered table-specs on drop, even if the test panics.
+struct RegisteredTableSpecsGuard(Vec<TableSpec>);
+
+impl RegisteredTableSpecsGuard {
+    fn new() -> Self {
+        Self(registered_table_specs())
+    }
+}
+
+impl Drop for RegisteredTableSpecsGuard {
+    fn drop(&mut self) {
+        for spec in registered_table_specs() {
+            unregister_table_spec(&spec.table_name);
+        }
+        for spec in &self.0 {
+            register_table_spec(spec.clone());
+        }
+    }
+}
+
+#[serial]
+#[test]
+fn should_replace_registered_spec_of_same_table() {
+    let _guard = RegisteredTableSpecsGuard::new();
+    let mut items_spec = default_table_specs().remove(1);
+    items_spec.global_secondary_indexes.clear();
+
+    register_table_spec(orders_spec());
+    register_table_spec(items_spec.clone());
+    let registered = registered_table_specs();
+
+    assert_eq!(registered.len(), 4);
+    assert_eq!(registered[1], items_spec);
+    assert_eq!(registered[3], orders_spec());
+}
+
+#[serial]
+#[tokio::test]
+async fn should_create_table_of_spec() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .with_container_name("localstack-test-api-table-spec")
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+    let client = get_dynamodb_client(&localstack);
+
+    let created = create_table(&client, &orders_spec()).await.unwrap();
+    let created_again = create_table(&client, &orders_spec()).await.unwrap();
+
+    assert!(created);
+    assert!(!created_again);
+    let table = client
+        .describe_table()
+        .table_name("orders")
+        .send()
+        .await
+        .unwrap()
+        .table
+        .unwrap();
+    assert_eq!(
+        table.local_secondary_indexes()[0]
+            .projection()
+            .and_then(|projection| projection.projection_type()),
+        Some(&ProjectionType::KeysOnly)
+    );
+    assert_eq!(
+        table
+            .stream_specification()
+            .and_then(|stream| stream.stream_view_type()),
+        Some(&StreamViewType::NewAndOldImages)
+    );
+    let time_to_live = client
+        .describe_time_to_live()
+        .table_name("orders")
+        .send()
+        .await
+        .unwrap()
+        .time_to_live_description
+        .unwrap();
+    assert_eq!(time_to_live.attribute_name(), Some("expires_at"));
+    assert_eq!(
+        time_to_live.time_to_live_status(),
+        Some(&TimeToLiveStatus::Enabled)
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn should_truncate_table_of_any_key_schema_for_reset() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .with_container_name("localstack-test-api-truncate")
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+    let client = get_dynamodb_client(&localstack);
+    create_table(&client, &orders_spec()).await.unwrap();
+    for created_at in 0..30 {
+        client
+            .put_item()
+            .table_name("orders")
+            .item("pk", AttributeValue::S("order#1".to_string()))
+            .item("created_at", AttributeValue::N(created_at.to_string()))
+            .item("status", AttributeValue::S("SHIPPED".to_string()))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    reset_with(&client, ResetStrategy::Truncate).await.unwrap();
+
+    let scan_output = client.scan().table_name("orders").send().await.unwrap();
+    assert_eq!(scan_output.count, 0);
+}
@@ -1,26 +1,53 @@
+use aws_sdk_sqs::types::QueueAttributeName;
+use bollard::Docker;
+use bollard::container::RemoveContainerOptions;
 use serial_test::serial;
-use test_api::localstack::{get_dynamodb_client, spin_up_localstack_with_services};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use test_api::Error;
+use test_api::localstack::{
+    LOCALSTACK_ENDPOINT_ENV_VAR, LocalStackBuilder, PERSISTENCE_DIR, Snapshot, get_dynamodb_client,
+    get_sqs_client,
+};
 
 #[serial]
 #[tokio::test]
 async fn should_expose_test_host_and_port() {
-    let container = spin_up_localstack_with_services(&[]).await;
+    // Reuse, e.g. enabled via TEST_API_REUSE_CONTAINER, would attach to a running container.
+    let localstack = LocalStackBuilder::new()
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+    let container = localstack
+        .container()
+        .expect("should have started a container");
 
     let host_ip = container.get_host().await.ok();
     let host_port = container.get_host_port_ipv4(4566).await.ok();
 
     assert_eq!(host_ip.unwrap().to_string(), "localhost");
-    assert_eq!(host_port.unwrap(), 4566);
+    assert!(host_port.is_some());
+    assert_eq!(
+        localstack.endpoint_url(),
+        format!("http://localhost:{}", host_port.unwrap())
+    );
 
-    drop(container);
+    drop(localstack);
 }
 
 #[serial]
 #[tokio::test]
 async fn should_spin_up_localstack() {
-    let container = spin_up_localstack_with_services(&["dynamodb"]).await;
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .start()
+        .await
+        .unwrap();
 
-    match get_dynamodb_client().await.list_tables().send().await {
+    match get_dynamodb_client(&localstack).list_tables().send().await {
         Ok(_) => {}
         Err(e) => {
             eprintln!("{:?}", e);
@@ -28,5 +55,581 @@ async fn should_spin_up_localstack() {
         }
     }
 
-    drop(container);
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_apply_builder_config() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .with_region("us-east-1")
+        .with_env_var("DEBUG", "1")
+        .start()
+        .await
+        .unwrap();
+
+    assert_eq!(localstack.region(), "us-east-1");
+    assert_eq!(
+        localstack
+            .aws_config()
+            .region()
+            .map(|region| region.to_string()),
+        Some("us-east-1".to_string())
+    );
+
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_attach_to_external_endpoint() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .start()
+        .await
+        .unwrap();
+
+    let external = LocalStackBuilder::new()
+        .with_external_endpoint(localstack.endpoint_url())
+        .start()
+        .await
+        .unwrap();
+
+    assert!(external.container().is_none());
+    assert_eq!(external.endpoint_url(), localstack.endpoint_url());
+    assert!(
+        get_dynamodb_client(&external)
+            .list_tables()
+            .send()
+            .await
+            .is_ok()
+    );
+
+    drop(external);
+    drop(localstack);
+}
+
+#[tokio::test]
+async fn should_list_services_not_ready_within_readiness_timeout() {
+    let err = LocalStackBuilder::new()
+        .with_services(["dynamodb", "sqs"])
+        .with_external_endpoint("http://localhost:1")
+        .with_readiness_timeout(Duration::from_secs(1))
+        .start()
+        .await
+        .unwrap_err();
+
+    match err {
+        Error::NotReady { services, .. } => assert_eq!(services, vec!["dynamodb", "sqs"]),
+        other => panic!("expected 'Error::NotReady' but got: {other}"),
+    }
+}
+
+#[serial]
+#[tokio::test]
+async fn should_capture_container_logs() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["dynamodb"])
+        .start()
+        .await
+        .unwrap();
+
+    let logs = localstack.logs().await.unwrap();
+
+    assert!(logs.to_string().contains("Ready."));
+
+    drop(localstack);
+}
+
+#[tokio::test]
+async fn should_reject_account_id_not_consisting_of_12_digits() {
+    let err = LocalStackBuilder::new()
+        .with_account_id("1234")
+        .start()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Config(_)));
+}
+
+#[serial]
+#[tokio::test]
+async fn should_create_resources_in_configured_account_and_region() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_region("us-west-2")
+        .with_account_id("123456789012")
+        .start()
+        .await
+        .unwrap();
+    let sqs_client = get_sqs_client(&localstack);
+    sqs_client
+        .create_queue()
+        .queue_name("regional_queue")
+        .send()
+        .await
+        .unwrap();
+
+    let attributes = sqs_client
+        .get_queue_attributes()
+        .queue_url(localstack.queue_url("regional_queue"))
+        .attribute_names(QueueAttributeName::QueueArn)
+        .send()
+        .await
+        .unwrap()
+        .attributes
+        .unwrap();
+
+    assert_eq!(
+        attributes[&QueueAttributeName::QueueArn],
+        localstack.arn("sqs", "regional_queue")
+    );
+    assert_eq!(
+        localstack.arn("sqs", "regional_queue"),
+        "arn:aws:sqs:us-west-2:123456789012:regional_queue"
+    );
+
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_share_clients_of_any_service() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["s3", "ssm"])
+        .start()
+        .await
+        .unwrap();
+
+    localstack
+        .client::<aws_sdk_s3::Client>()
+        .create_bucket()
+        .bucket("raw-pages")
+        .send()
+        .await
+        .unwrap();
+    let buckets = localstack
+        .client::<aws_sdk_s3::Client>()
+        .list_buckets()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(buckets.buckets().len(), 1);
+
+    let ssm_client = localstack.client::<aws_sdk_ssm::Client>();
+    ssm_client
+        .put_parameter()
+        .name("/scraper/base-url")
+        .value("https://example.com")
+        .send()
+        .await
+        .unwrap();
+    let parameter = ssm_client
+        .get_parameter()
+        .name("/scraper/base-url")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        parameter.parameter().and_then(|p| p.value()),
+        Some("https://example.com")
+    );
+
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_keep_container_alive_if_requested() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-keep-alive")
+        .with_keep_alive(true)
+        .start()
+        .await
+        .unwrap();
+    drop(localstack);
+
+    let running = Docker::connect_with_defaults()
+        .unwrap()
+        .inspect_container("localstack-test-api-keep-alive", None)
+        .await
+        .unwrap()
+        .state
+        .and_then(|state| state.running);
+    remove_container("localstack-test-api-keep-alive").await;
+
+    assert_eq!(running, Some(true));
+}
+
+#[serial]
+#[tokio::test]
+async fn should_mount_persistence_dir() {
+    let dir = std::env::temp_dir().join(format!("test-api-persistence-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-persistence")
+        .with_persistence(&dir)
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+
+    let container = Docker::connect_with_defaults()
+        .unwrap()
+        .inspect_container("localstack-test-api-persistence", None)
+        .await
+        .unwrap();
+    drop(localstack);
+    let is_dir_created = dir.is_dir();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(is_dir_created);
+    let env = container
+        .config
+        .and_then(|config| config.env)
+        .unwrap_or_default();
+    assert!(env.contains(&"PERSISTENCE=1".to_string()), "env: {env:?}");
+    let mount = container
+        .mounts
+        .unwrap_or_default()
+        .into_iter()
+        .find(|mount| mount.destination.as_deref() == Some(PERSISTENCE_DIR));
+    let source = mount.and_then(|mount| mount.source).map(PathBuf::from);
+    assert_eq!(source, Some(dir));
+}
+
+#[serial]
+#[tokio::test]
+async fn should_reuse_running_compatible_container() {
+    let builder = || {
+        LocalStackBuilder::new()
+            .with_services(["sqs"])
+            .with_container_name("localstack-test-api-reuse")
+            .with_reuse(true)
+    };
+    let localstack = builder().start().await.unwrap();
+    assert!(localstack.container().is_some());
+    get_sqs_client(&localstack)
+        .create_queue()
+        .queue_name("reused_queue")
+        .send()
+        .await
+        .unwrap();
+    drop(localstack);
+
+    let reused = builder().start().await.unwrap();
+    let queue_url = get_sqs_client(&reused)
+        .get_queue_url()
+        .queue_name("reused_queue")
+        .send()
+        .await;
+    remove_container("localstack-test-api-reuse").await;
+
+    assert!(reused.container().is_none());
+    assert!(queue_url.is_ok());
+}
+
+#[serial]
+#[tokio::test]
+async fn should_share_container_of_same_name_and_configuration() {
+    let builder = || {
+        LocalStackBuilder::new()
+            .with_services(["sqs"])
+            .with_container_name("localstack-test-api-shared")
+            .with_reuse(false)
+    };
+    let localstack = builder().start().await.unwrap();
+    let shared = builder().start().await.unwrap();
+
+    assert!(localstack.container().is_some());
+    assert!(shared.container().is_none());
+    assert_eq!(shared.endpoint_url(), localstack.endpoint_url());
+
+    drop(shared);
+    let queue = get_sqs_client(&localstack)
+        .create_queue()
+        .queue_name("still_running_queue")
+        .send()
+        .await;
+    assert!(queue.is_ok());
+
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_provision_environment_once_per_container() {
+    let builder = || {
+        LocalStackBuilder::new()
+            .with_services(["sqs"])
+            .with_container_name("localstack-test-api-provision")
+    };
+    let localstack = builder().start().await.unwrap();
+    let shared = builder().start().await.unwrap();
+    let provisioned = AtomicUsize::new(0);
+    let count_provisioning = || async {
+        provisioned.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    };
+
+    localstack
+        .provision("queues", count_provisioning)
+        .await
+        .unwrap();
+    shared
+        .provision("queues", count_provisioning)
+        .await
+        .unwrap();
+
+    assert_eq!(provisioned.load(Ordering::SeqCst), 1);
+
+    drop(shared);
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_provision_environment_once_per_external_endpoint() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-provision-external")
+        .start()
+        .await
+        .unwrap();
+    let external = || {
+        LocalStackBuilder::new()
+            .with_services(["sqs"])
+            .with_external_endpoint(localstack.endpoint_url())
+            .start()
+    };
+    let first = external().await.unwrap();
+    let second = external().await.unwrap();
+    // A reused container keeps its session, so the environment is unique per run.
+    let environment = format!("queues-{}", std::process::id());
+    let provisioned = AtomicUsize::new(0);
+    let count_provisioning = || async {
+        provisioned.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    };
+
+    first
+        .provision(&environment, count_provisioning)
+        .await
+        .unwrap();
+    second
+        .provision(&environment, count_provisioning)
+        .await
+        .unwrap();
+
+    assert_eq!(provisioned.load(Ordering::SeqCst), 1);
+
+    drop(second);
+    drop(first);
+    drop(localstack);
+}
+
+const UNREACHABLE_DOCKER_HOST: &str = "unix:///nonexistent/docker.sock";
+
+/// Runs [`report_unreachable_docker`] in a child process, because setting `DOCKER_HOST` in this one
+/// races with the other tests reading the environment.
+#[test]
+fn should_report_unreachable_docker_up_front() {
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["report_unreachable_docker", "--exact", "--ignored"])
+        .env("DOCKER_HOST", UNREACHABLE_DOCKER_HOST)
+        .env_remove(LOCALSTACK_ENDPOINT_ENV_VAR)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "child process failed: {stdout}");
+    assert!(
+        stdout.contains("1 passed"),
+        "child process ran no test: {stdout}"
+    );
+}
+
+#[ignore = "run by should_report_unreachable_docker_up_front with an unreachable DOCKER_HOST"]
+#[tokio::test]
+async fn report_unreachable_docker() {
+    assert_eq!(
+        std::env::var("DOCKER_HOST").as_deref(),
+        Ok(UNREACHABLE_DOCKER_HOST)
+    );
+
+    let result = LocalStackBuilder::new()
+        .with_container_name("localstack-test-api-no-docker")
+        .start()
+        .await;
+
+    match result {
+        Err(e @ Error::DockerUnavailable { .. }) => {
+            assert!(e.to_string().contains("TEST_API_SKIP_WITHOUT_DOCKER"));
+        }
+        other => panic!("expected Error::DockerUnavailable but got {other:?}"),
+    }
+}
+
+async fn remove_container(name: &str) {
+    let options = RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    };
+    let _ = Docker::connect_with_defaults()
+        .unwrap()
+        .remove_container(name, Some(options))
+        .await;
+}
+
+#[serial]
+#[tokio::test]
+async fn should_diagnose_host_port_bound_by_other_container() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-port-owner")
+        .with_host_port(45660)
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+
+    let result = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-port-conflict")
+        .with_host_port(45660)
+        .with_startup_attempts(1)
+        .start()
+        .await;
+
+    match result {
+        Err(Error::ContainerStart {
+            attempts,
+            diagnosis,
+            ..
+        }) => {
+            assert_eq!(attempts, 1);
+            assert!(
+                diagnosis
+                    .iter()
+                    .any(|cause| cause.contains("'localstack-test-api-port-owner'")),
+                "unexpected diagnosis: {diagnosis:?}"
+            );
+        }
+        other => panic!("expected Error::ContainerStart but got {other:?}"),
+    }
+    drop(localstack);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_run_init_scripts_before_returning() {
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-init-scripts")
+        .with_init_script(
+            "create-queue.sh",
+            "#!/bin/bash\nawslocal sqs create-queue --queue-name created-by-init-script\n",
+        )
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+
+    let queue = localstack
+        .client::<aws_sdk_sqs::Client>()
+        .get_queue_url()
+        .queue_name("created-by-init-script")
+        .send()
+        .await;
+
+    assert!(queue.is_ok(), "queue of init-script missing: {queue:?}");
+}
+
+#[serial]
+#[tokio::test]
+async fn should_fail_start_on_failing_init_script() {
+    let result = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-failing-init-script")
+        .with_init_script("fail.sh", "#!/bin/bash\nexit 1\n")
+        .with_reuse(false)
+        .start()
+        .await;
+
+    match result {
+        Err(Error::InitScripts { failed, .. }) => assert_eq!(failed, ["fail.sh"]),
+        other => panic!("expected Error::InitScripts but got {other:?}"),
+    }
+}
+
+#[test]
+fn should_compute_fingerprint_stable_across_builds() {
+    let fingerprint = Snapshot::new("environment.zip")
+        .with_fingerprint("v1")
+        .fingerprint();
+
+    assert_eq!(
+        fingerprint,
+        "964b4bf2932b9233fd8546eb5a046674ff40fafc654d99b259fb6b4eb333813f"
+    );
+}
+
+#[test]
+fn should_invalidate_snapshot_of_other_fingerprint() {
+    let dir = std::env::temp_dir().join(format!("test-api-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("environment.zip");
+    let snapshot = Snapshot::new(&path).with_fingerprint("v1");
+    std::fs::write(&path, "state").unwrap();
+    std::fs::write(
+        dir.join("environment.zip.fingerprint"),
+        snapshot.fingerprint(),
+    )
+    .unwrap();
+
+    let is_current = snapshot.is_current();
+    let is_other_current = Snapshot::new(&path).with_fingerprint("v2").is_current();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(is_current);
+    assert!(!is_other_current);
+}
+
+#[serial]
+#[tokio::test]
+async fn should_provision_without_snapshot_support() {
+    let snapshot_dir =
+        std::env::temp_dir().join(format!("test-api-snapshots-{}", std::process::id()));
+    let snapshot = Snapshot::new(snapshot_dir.join("queues.zip")).with_fingerprint("v1");
+    let localstack = LocalStackBuilder::new()
+        .with_services(["sqs"])
+        .with_container_name("localstack-test-api-snapshot")
+        .with_reuse(false)
+        .start()
+        .await
+        .unwrap();
+
+    localstack
+        .provision_from_snapshot("queues", &snapshot, || async {
+            get_sqs_client(&localstack)
+                .create_queue()
+                .queue_name("provisioned")
+                .send()
+                .await
+                .map_err(aws_sdk_sqs::Error::from)?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+    let queue = get_sqs_client(&localstack)
+        .get_queue_url()
+        .queue_name("provisioned")
+        .send()
+        .await;
+    let _ = std::fs::remove_dir_all(&snapshot_dir);
+    assert!(queue.is_ok(), "provisioned queue missing: {queue:?}");
 }
@@ -0,0 +1,95 @@
+use test_api::logging::{EventMatcher, LogFormat, TracingCapture, TracingSetup};
+use tracing::Level;
+use tracing::instrument::WithSubscriber;
+
+#[test]
+fn should_install_global_subscriber_only_once() {
+    let first = TracingSetup::new()
+        .with_format(LogFormat::Json)
+        .with_test_writer()
+        .init();
+    let second = TracingSetup::new().init();
+
+    assert!(first.is_ok());
+    assert!(second.is_err());
+}
+
+#[tokio::test]
+async fn should_capture_events_with_fields_and_spans() {
+    let capture = TracingCapture::new();
+
+    capture
+        .capture(async {
+            let span = tracing::info_span!("ingest", source = "test");
+            let _entered = span.enter();
+            tracing::warn!(item_id = "X", price = 42, "Dropping item");
+        })
+        .await;
+
+    capture.assert_emitted(
+        &EventMatcher::at(Level::WARN)
+            .with_field("item_id", "X")
+            .with_field("price", 42)
+            .with_message_containing("Dropping")
+            .in_span("ingest"),
+    );
+    capture.assert_not_emitted(&EventMatcher::at(Level::ERROR));
+    assert_eq!(capture.spans().len(), 1);
+    assert_eq!(capture.spans()[0].fields["source"], "test");
+}
+
+#[tokio::test]
+async fn should_not_capture_events_outside_of_capture() {
+    let capture = TracingCapture::new();
+
+    tracing::warn!(item_id = "X", "Dropping item");
+    capture.capture(async {}).await;
+
+    assert!(capture.events().is_empty());
+}
+
+#[tokio::test]
+async fn should_forward_to_previously_active_subscriber() {
+    let outer = TracingCapture::new();
+    let inner = TracingCapture::new();
+
+    outer
+        .capture(async {
+            inner
+                .capture(async {
+                    let span = tracing::info_span!("ingest");
+                    let _entered = span.enter();
+                    tracing::warn!(item_id = "X", "Dropping item");
+                })
+                .await;
+        })
+        .await;
+
+    let matcher = EventMatcher::at(Level::WARN)
+        .with_field("item_id", "X")
+        .in_span("ingest");
+    inner.assert_emitted(&matcher);
+    outer.assert_emitted(&matcher);
+}
+
+#[tokio::test]
+async fn should_capture_spawned_tasks_only_with_current_subscriber() {
+    let capture = TracingCapture::new();
+
+    capture
+        .capture(async {
+            tokio::spawn(async { tracing::warn!(task = "plain", "Dropping item") })
+                .await
+                .unwrap();
+            tokio::spawn(
+                async { tracing::warn!(task = "with_subscriber", "Dropping item") }
+                    .with_current_subscriber(),
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+
+    capture.assert_not_emitted(&EventMatcher::new().with_field("task", "plain"));
+    capture.assert_emitted(&EventMatcher::new().with_field("task", "with_subscriber"));
+}
@@ -1,15 +1,20 @@
+use bollard::Docker;
 use item_core::item_data::ItemData;
 use item_core::item_model::ItemModel;
+use serial_test::serial;
 use std::time::Duration;
+use test_api::dynamodb::get_localstack_dynamodb;
 use test_api::generator::Generator;
-use test_api::localstack::{get_lambda_client, get_sqs_client};
-use test_api::sqs_lambda_dynamodb::{LAMBDA_NAME, WRITE_LAMBDA_QUEUE_URL};
+use test_api::localstack::{get_dynamodb_client, get_lambda_client};
+use test_api::sqs_lambda_dynamodb::{
+    LAMBDA_NAME, get_localstack_sqs_lambda_dynamodb, get_write_lambda_queue_url,
+};
 use test_api_macros::blitzfilter_data_ingestion_test;
 use tokio::time::sleep;
 
 #[blitzfilter_data_ingestion_test]
 async fn should_enable_lambda_service_and_upload_lambda() {
-    let list_functions_res = get_lambda_client().await.list_functions().send().await;
+    let list_functions_res = get_lambda_client(localstack).list_functions().send().await;
     assert!(list_functions_res.is_ok());
 
     let list_functions_opt = list_functions_res.unwrap().functions;
@@ -21,13 +26,28 @@ async fn should_enable_lambda_service_and_upload_lambda() {
     );
 }
 
+#[blitzfilter_data_ingestion_test]
+async fn should_skip_existing_resources_for_repeated_init() {
+    test_api::sqs_lambda_dynamodb::init(localstack)
+        .await
+        .unwrap();
+
+    let mappings = get_lambda_client(localstack)
+        .list_event_source_mappings()
+        .function_name(LAMBDA_NAME)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(mappings.event_source_mappings().len(), 1);
+}
+
 #[blitzfilter_data_ingestion_test]
 async fn should_insert_msg_in_q_then_trigger_lambda() {
     let item: ItemData = ItemModel::generate().into();
-    let sqs_client = get_sqs_client().await;
+    let queue_url = get_write_lambda_queue_url(localstack);
     sqs_client
         .send_message()
-        .queue_url(WRITE_LAMBDA_QUEUE_URL)
+        .queue_url(&queue_url)
         .message_body(serde_json::to_string(&item).unwrap())
         .send()
         .await
@@ -39,10 +59,43 @@ async fn should_insert_msg_in_q_then_trigger_lambda() {
     // Check if queue is empty - someone else (Lambda) polled the event we previously sent
     let receive_res = sqs_client
         .receive_message()
-        .queue_url(WRITE_LAMBDA_QUEUE_URL)
+        .queue_url(&queue_url)
         .send()
         .await;
     assert!(receive_res.is_ok());
     let received_msgs_opt = receive_res.unwrap().messages;
     assert!(received_msgs_opt.is_none());
 }
+
+#[serial]
+#[tokio::test]
+async fn should_run_environments_side_by_side() {
+    let dynamodb_localstack = get_localstack_dynamodb().await.unwrap();
+    let dynamodb_container_id = container_id(test_api::dynamodb::CONTAINER_NAME).await;
+
+    let sqs_lambda_dynamodb_localstack = get_localstack_sqs_lambda_dynamodb().await.unwrap();
+
+    assert_ne!(
+        dynamodb_localstack.endpoint_url(),
+        sqs_lambda_dynamodb_localstack.endpoint_url()
+    );
+    assert_eq!(
+        container_id(test_api::dynamodb::CONTAINER_NAME).await,
+        dynamodb_container_id
+    );
+    let list_tables_output = get_dynamodb_client(dynamodb_localstack)
+        .list_tables()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(list_tables_output.table_names().len(), 3);
+}
+
+async fn container_id(name: &str) -> Option<String> {
+    Docker::connect_with_defaults()
+        .unwrap()
+        .inspect_container(name, None)
+        .await
+        .unwrap()
+        .id
+}